@@ -1,18 +1,23 @@
-#![allow(unused_must_use, nonstandard_style)]
+#![allow(nonstandard_style)]
 extern crate bml_grapher;
 use rand::{rng, Rng};
 
-use bml_grapher::{math::Vec2D, Ctx, GraphCtx, GraphSettings, Rgba, SnorfWindow};
+use bml_grapher::{math::{Transform, Vec2D}, Ctx, GraphCtx, GraphSettings, Layer, Layers, SnorfWindow};
 
 const WIDTH: usize = 720; // 480
 const HEIGHT: usize = 540; // 540
 
+// The palette, not every color is used at once
+#[allow(dead_code)]
 const BLACK:u32 = 0x000000;
 const WHITE:u32 = 0xffffff ;
 const RED:u32 = 0xff0800 ;
+#[allow(dead_code)]
 const GREY: u32 = 0xf5f5f5;  // 0xc2c2c2
 const BLUE: u32 = 0x4328ed;
+#[allow(dead_code)]
 const YELLOW:u32 = 0xf9f034;
+#[allow(dead_code)]
 const GREEN:u32 =0x1be81b;
 
 
@@ -34,13 +39,13 @@ impl Node {
         Node::new(x-0.5), Node::new(x + 0.5)]
     }
     // Maximises the child according to evaluation function f.
-    fn get_best_child<F: Fn(f32) -> f32>(children: &Vec<Node>, f: &F) -> Node {
-        assert!( children.len() > 0);
+    #[allow(dead_code)] // only hill_climbing uses it
+    fn get_best_child<F: Fn(f32) -> f32>(children: &[Node], f: &F) -> Node {
+        assert!( !children.is_empty());
 
         let mut best = children[0];
 
-        for i in 1..children.len() {
-            let child = children[i];
+        for &child in &children[1..] {
             if f(child.x) > f(best.x) {
                 best = child;
             }
@@ -50,13 +55,21 @@ impl Node {
 }
 
 
-fn hill_climbing<F: Fn(f32) -> f32>(window: &mut SnorfWindow, ctx: &mut GraphCtx, offset:usize, border_offsets: [f32;4],start_node: Node, f: F) -> Node  {
+// Markers go on the overlay layer, so the graph under them never has to be drawn again
+fn plot(layers: &mut Layers, settings: &GraphSettings, x: f32, y: f32, color: u32) {
+    let mut overlay = GraphCtx::new(layers.get(Layer::Overlay), settings.clone());
+    // The search can wander off the screen, those markers just aren't drawn
+    let _ = overlay.plot_on_graph(&Vec2D::new(x, y), 5, color);
+}
+
+// The simpler search, main runs simulated_annealing instead
+#[allow(dead_code)]
+fn hill_climbing<F: Fn(f32) -> f32>(window: &mut SnorfWindow, layers: &mut Layers, settings: &GraphSettings, start_node: Node, f: F) -> Node  {
     let mut current = start_node;
 
     loop {
         // ---------------- Display stuff
-        ctx.plot_on_graph(&Vec2D::new(current.x, f(current.x)), 
-            5, BLUE) ;
+        plot(layers, settings, current.x, f(current.x), BLUE);
 
         std::thread::sleep(std::time::Duration::from_millis(500));
         println!("({},{})", current.x, f(current.x));
@@ -68,11 +81,10 @@ fn hill_climbing<F: Fn(f32) -> f32>(window: &mut SnorfWindow, ctx: &mut GraphCtx
         let best_neighbor = Node::get_best_child(&neighbors, &f);
         
         if f(best_neighbor.x) <= f(current.x) {
-            ctx.plot_on_graph(&Vec2D::new(current.x, f(current.x)), 
-            5, GREEN);
+            plot(layers, settings, current.x, f(current.x), GREEN);
             return current
         }
-        window.update(&ctx.ctx).unwrap();
+        window.update_layers(layers).unwrap();
 
 
         current = best_neighbor;
@@ -84,7 +96,7 @@ fn hill_climbing<F: Fn(f32) -> f32>(window: &mut SnorfWindow, ctx: &mut GraphCtx
 
 
 // Make sure the evaluation function is minimal when out of bounds
-fn simulated_annealing<F: Fn(f32) -> f32>(window: &mut SnorfWindow, ctx: &mut GraphCtx,start_node: Node, f: F) -> Node  {
+fn simulated_annealing<F: Fn(f32) -> f32>(window: &mut SnorfWindow, layers: &mut Layers, settings: &GraphSettings, start_node: Node, f: F) -> Node  {
     let mut current = start_node;
     
     //? Shedule determines the value of temperature T as a function of time
@@ -94,8 +106,7 @@ fn simulated_annealing<F: Fn(f32) -> f32>(window: &mut SnorfWindow, ctx: &mut Gr
     let mut t = 0.0; // time
     loop {
         // ---------------- Display stuff
-        ctx.plot_on_graph(&Vec2D::new(current.x, f(current.x)), 
-            5, BLUE);
+        plot(layers, settings, current.x, f(current.x), BLUE);
 
         std::thread::sleep(std::time::Duration::from_millis(100));
         //------------------
@@ -137,7 +148,7 @@ fn simulated_annealing<F: Fn(f32) -> f32>(window: &mut SnorfWindow, ctx: &mut Gr
 
         t += 0.5;
 
-        window.update(&ctx.ctx).unwrap();
+        window.update_layers(layers).unwrap();
 
     }
 
@@ -153,17 +164,15 @@ fn simulated_annealing<F: Fn(f32) -> f32>(window: &mut SnorfWindow, ctx: &mut Gr
 
 fn main() {
     let mut window = SnorfWindow::new("Grapher", WIDTH,HEIGHT, None);
-    let mut ctx = window.get_context();
+    let mut layers = window.get_layers();
 
     // Axis
     let axoff = 20; // axis offset
 
     let min_xnum = 0.0; let max_xnum = 10.0;
     let min_ynum = 0.0; let max_ynum = 10.0;
-    let border_offsets = [min_xnum,max_xnum,min_ynum,max_ynum];
 
     let settings = GraphSettings::new(axoff, min_xnum, max_xnum, min_ynum, max_ynum);
-    let mut graph_ctx = GraphCtx::new(&mut ctx, settings);
 
     let f = |x: f32| {
         if x < 0.0|| x > max_xnum {0.0} // partwise function
@@ -172,34 +181,58 @@ fn main() {
 
     // let node = Node::
 
-    while window.is_open() {
-        graph_ctx.ctx.clear_rect(WHITE);
-
-        graph_ctx.ctx.set_thickness(2);
+    // Everything is recorded the first time, so it can be saved again at a higher resolution afterwards
+    let mut saved_2x = false;
+    for layer in [Layer::Background, Layer::Grid, Layer::Data] {
+        layers.get(layer).begin_recording();
+    }
 
-        // ctx.draw_line(&[50,50].into(), &[100,100].into(), RED);
-        // ctx.draw_text(&[200,200].into(), "Yo", 1);
+    // ------------------ Draw the plotter
+    // Only once, the background, grid and function stay the same. Only the overlay with the markers changes.
+    layers.get(Layer::Background).clear_rect(WHITE);
 
+    let mut grid = GraphCtx::new(layers.get(Layer::Grid), settings.clone());
+    grid.draw_axis(true, 1.0, min_xnum, max_xnum).unwrap();
+    grid.draw_axis(false,1.0, min_ynum, max_ynum).unwrap();
 
+    let mut data = GraphCtx::new(layers.get(Layer::Data), settings.clone());
+    data.ctx.set_thickness(2);
+    data.draw_graph(f, 1, axoff, 
+        min_xnum, max_xnum,
+        min_ynum, max_ynum, 
+        RED
+    ).unwrap();
 
-        // ------------------ Draw the plotter
-        
-        graph_ctx.draw_axis(true, 1.0, min_xnum, max_xnum);
-        graph_ctx.draw_axis(false,1.0, min_ynum, max_ynum);
+    // ctx.draw_line(&[50,50].into(), &[100,100].into(), RED);
+    // ctx.draw_text(&[200,200].into(), "Yo", 1);
 
-        graph_ctx.draw_graph(f, 1, axoff, 
-            min_xnum, max_xnum,
-            min_ynum, max_ynum, 
-            RED
-        );
+    while window.is_open() {
+        layers.get(Layer::Overlay).clear_transparent();
+        if !saved_2x {
+            layers.get(Layer::Overlay).begin_recording();
+        }
 
-        simulated_annealing(&mut window,&mut graph_ctx, 
+        simulated_annealing(&mut window, &mut layers, &settings,
             Node::new(2.0), f
         );
 
+        if !saved_2x {
+            let mut big = Ctx::headless(2*WIDTH, 2*HEIGHT);
+            for layer in Layer::ALL {
+                let trace = layers.get(layer).end_recording();
+                trace.replay(&mut big, Some(Transform::scaling(2.0, 2.0)));
+            }
+            let path = std::env::current_dir().unwrap_or_default().join("simulated_annealing_2x.png");
+            match big.save_png(&path) {
+                Ok(()) => println!("Saved the plot at 2x to {}", path.display()),
+                Err(e) => eprintln!("Couldn't save the plot at 2x to {}: {e}", path.display()),
+            }
+            saved_2x = true;
+        }
+
         // ctx.plot_on_graph(Vec2D::new(5.0,5.0), 3, BLUE, axoff, border_offsets);
 
 
-        window.update(&graph_ctx.ctx).unwrap();
+        window.update_layers(&mut layers).unwrap();
     }
 }
@@ -0,0 +1,60 @@
+// The clip region of a Ctx. Everything that is drawn gets limited to it, pixels that are only
+// partly inside (from an anti-aliased clip path) are blended by how much of them is inside.
+
+use std::sync::Arc;
+
+use crate::raster::Coverage;
+
+#[derive(Clone)]
+pub(crate) struct Clip {
+    // Everything is in rows of the buffer (from the top), so it doesn't matter what the origin of the ctx is.
+    // Pixels outside of [x0, row0, x1, row1] (inclusive) are always clipped, so a rectangle doesn't need a mask
+    bounds: [i32; 4],
+    // How much of each pixel is inside, laid out like the buffer. None if everything in the bounds is inside.
+    // It's shared so save() doesn't copy the whole thing
+    mask: Option<Arc<Vec<f32>>>,
+    width: usize,
+}
+
+impl Clip {
+    pub fn rect(bounds: [i32; 4], width: usize) -> Self {
+        Clip { bounds, mask: None, width }
+    }
+
+    /// Only the pixels given (x, row, coverage) are inside. `previous` is the clip it's being added to.
+    pub fn from_coverage(pixels: &Coverage, previous: Option<&Clip>, (width, height): (usize, usize)) -> Self {
+        let mut mask = vec![0.0; width * height];
+        let mut bounds = [i32::MAX, i32::MAX, i32::MIN, i32::MIN];
+
+        for &(x, row, c) in pixels {
+            if x < 0 || row < 0 || x as usize >= width || row as usize >= height {
+                continue;
+            }
+            let c = c * previous.map_or(1.0, |clip| clip.coverage(x as usize, row as usize));
+            if c > 0.0 {
+                mask[row as usize * width + x as usize] = c;
+                bounds = [bounds[0].min(x), bounds[1].min(row), bounds[2].max(x), bounds[3].max(row)];
+            }
+        }
+        Clip { bounds, mask: Some(Arc::new(mask)), width }
+    }
+
+    /// What's left of this clip inside the rectangle
+    pub fn intersect_rect(&self, [x0, row0, x1, row1]: [i32; 4]) -> Self {
+        let [a0, b0, a1, b1] = self.bounds;
+        Clip { bounds: [a0.max(x0), b0.max(row0), a1.min(x1), b1.min(row1)], ..self.clone() }
+    }
+
+    /// How much of the pixel is inside, from 0.0 to 1.0
+    pub fn coverage(&self, x: usize, row: usize) -> f32 {
+        let [x0, row0, x1, row1] = self.bounds;
+        let (x, row) = (x as i32, row as i32);
+        if x < x0 || row < row0 || x > x1 || row > row1 {
+            return 0.0;
+        }
+        match &self.mask {
+            Some(mask) => mask[row as usize * self.width + x as usize],
+            None => 1.0,
+        }
+    }
+}
@@ -0,0 +1,339 @@
+// Loading images from PNG, BMP and PPM files into an Image (0RGB like Ctx, so it can be drawn with draw_image).
+// PNG goes through the png crate, BMP and PPM are simple enough to read by hand.
+
+use std::path::Path;
+
+use crate::error::Error;
+use crate::image::Image;
+
+impl Image {
+    /// Loads a PNG, BMP or PPM (or PGM) file, the format is worked out from the start of the file.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let bytes = std::fs::read(path)?;
+        Self::from_bytes(&bytes)
+    }
+
+    /// Same as open, for a file that's already in memory.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        match bytes {
+            [0x89, b'P', b'N', b'G', ..] => Self::from_png(bytes),
+            [b'B', b'M', ..] => Self::from_bmp(bytes),
+            [b'P', b'2' | b'3' | b'5' | b'6', ..] => Self::from_ppm(bytes),
+            _ => Err(invalid("not a PNG, BMP or PPM file")),
+        }
+    }
+
+    /// Every kind of PNG works, images with transparency keep their alpha.
+    pub fn from_png(bytes: &[u8]) -> Result<Self, Error> {
+        let mut decoder = png::Decoder::new(bytes);
+        // Palettes, low bit depths and 16 bit channels all become 8 bit gray or rgb (with alpha)
+        decoder.set_transformations(png::Transformations::normalize_to_color8());
+        let mut reader = decoder.read_info()?;
+        let mut data = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut data)?;
+
+        let (w, h) = (info.width as usize, info.height as usize);
+        let samples = info.color_type.samples();
+        let has_alpha = matches!(info.color_type, png::ColorType::GrayscaleAlpha | png::ColorType::Rgba);
+        let mut image = Image::new(Vec::with_capacity(w * h), w, h);
+        let mut alpha: Vec<u8> = Vec::new();
+
+        for y in 0..h {
+            let line = &data[y * info.line_size..];
+            for x in 0..w {
+                let px = &line[x * samples..(x + 1) * samples];
+                let (r, g, b, a) = match info.color_type {
+                    png::ColorType::Grayscale => (px[0], px[0], px[0], 255),
+                    png::ColorType::GrayscaleAlpha => (px[0], px[0], px[0], px[1]),
+                    png::ColorType::Rgb => (px[0], px[1], px[2], 255),
+                    png::ColorType::Rgba => (px[0], px[1], px[2], px[3]),
+                    png::ColorType::Indexed => return Err(invalid("PNG palette wasn't expanded")),
+                };
+                image.buf.push(rgb(r, g, b));
+                alpha.push(a);
+            }
+        }
+        if has_alpha {
+            image.alpha = Some(alpha);
+        }
+        Ok(image)
+    }
+
+    /// Uncompressed BMP files with 1, 4, 8, 16, 24 or 32 bits per pixel (RLE compression isn't supported).
+    pub fn from_bmp(bytes: &[u8]) -> Result<Self, Error> {
+        let u16_at = |i: usize| bytes.get(i..i + 2).map(|b| u16::from_le_bytes([b[0], b[1]])).ok_or_else(|| invalid("BMP file is cut short"));
+        let u32_at = |i: usize| bytes.get(i..i + 4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]])).ok_or_else(|| invalid("BMP file is cut short"));
+
+        let data_offset = u32_at(10)? as usize;
+        let header_size = u32_at(14)? as usize;
+        if header_size < 40 {
+            return Err(invalid("old OS/2 BMP files aren't supported"));
+        }
+        let (width, height) = (u32_at(18)? as i32, u32_at(22)? as i32);
+        let bpp = u16_at(28)? as usize;
+        // Before anything is worked out from it, 0 would make the rows empty
+        if ![1, 4, 8, 16, 24, 32].contains(&bpp) {
+            return Err(invalid(&format!("{bpp} bits per pixel BMP files aren't supported")));
+        }
+        let compression = u32_at(30)?;
+        if width <= 0 || height == 0 {
+            return Err(invalid("BMP has no pixels"));
+        }
+        // Rows are stored bottom-up, unless the height is negative
+        let top_down = height < 0;
+        let (w, h) = (width as usize, height.unsigned_abs() as usize);
+
+        // Where the red, green, blue and alpha bits are for 16 and 32 bit pixels
+        let mut masks = match bpp {
+            16 => [0x7c00, 0x03e0, 0x001f, 0],
+            _ => [0xff0000, 0x00ff00, 0x0000ff, 0],
+        };
+        match compression {
+            0 => {},
+            3 | 6 => { // BI_BITFIELDS, BI_ALPHABITFIELDS: the masks are right after the 40 byte header
+                masks = [u32_at(54)?, u32_at(58)?, u32_at(62)?, 0];
+                if compression == 6 || header_size >= 56 {
+                    masks[3] = u32_at(66)?;
+                }
+            },
+            _ => return Err(invalid("compressed BMP files aren't supported")),
+        }
+
+        let mut palette: Vec<u32> = Vec::new();
+        if bpp <= 8 {
+            let count = match u32_at(46)? { 0 => 1 << bpp, n => n as usize };
+            for i in 0..count.min(256) {
+                let entry = 14 + header_size + i * 4;
+                let b = bytes.get(entry..entry + 3).ok_or_else(|| invalid("BMP palette is cut short"))?;
+                palette.push(rgb(b[2], b[1], b[0]));
+            }
+        }
+
+        // Every row is padded to a multiple of 4 bytes
+        let row_size = (bpp * w).div_ceil(32) * 4;
+        let end = h.checked_mul(row_size).and_then(|n| n.checked_add(data_offset));
+        if end.is_none_or(|end| end > bytes.len()) {
+            return Err(invalid("BMP pixels are cut short"));
+        }
+
+        // Only now that the pixels are known to be in the file, so a made up size can't reserve lots of memory
+        let pixel_count = w.checked_mul(h).ok_or_else(|| invalid("BMP is too big"))?;
+        let mut image = Image::new(Vec::with_capacity(pixel_count), w, h);
+        let mut alpha: Vec<u8> = Vec::with_capacity(pixel_count);
+        for y in 0..h {
+            let src_row = if top_down { y } else { h - 1 - y };
+            let line = &bytes[data_offset + src_row * row_size..data_offset + (src_row + 1) * row_size];
+            for x in 0..w {
+                let (color, a) = match bpp {
+                    1 | 4 | 8 => {
+                        let bit = x * bpp;
+                        let index = (line[bit / 8] >> (8 - bpp - bit % 8)) & ((1u16 << bpp) - 1) as u8;
+                        (palette.get(index as usize).copied().unwrap_or(0), 255)
+                    },
+                    24 => (rgb(line[x * 3 + 2], line[x * 3 + 1], line[x * 3]), 255),
+                    16 | 32 => {
+                        let value = if bpp == 16 {
+                            u16::from_le_bytes([line[x * 2], line[x * 2 + 1]]) as u32
+                        }else {
+                            u32::from_le_bytes([line[x * 4], line[x * 4 + 1], line[x * 4 + 2], line[x * 4 + 3]])
+                        };
+                        let [r, g, b, a] = masks.map(|mask| channel(value, mask));
+                        (rgb(r, g, b), a)
+                    },
+                    _ => unreachable!("bpp was checked above"),
+                };
+                image.buf.push(color);
+                alpha.push(a);
+            }
+        }
+        // Lots of programs write an alpha of 0 everywhere, that's treated as no alpha
+        if masks[3] != 0 && alpha.iter().any(|a| *a != 0) {
+            image.alpha = Some(alpha);
+        }
+        Ok(image)
+    }
+
+    /// Binary (P6) and plain text (P3) PPM files, and their gray versions (P5 and P2) from PGM.
+    pub fn from_ppm(bytes: &[u8]) -> Result<Self, Error> {
+        let (binary, channels) = match bytes.get(0..2) {
+            Some(b"P2") => (false, 1),
+            Some(b"P3") => (false, 3),
+            Some(b"P5") => (true, 1),
+            Some(b"P6") => (true, 3),
+            _ => return Err(invalid("not a PPM file")),
+        };
+        let mut pos = 2;
+        let w = header_number(bytes, &mut pos)?;
+        let h = header_number(bytes, &mut pos)?;
+        let max = header_number(bytes, &mut pos)?;
+        if max == 0 || max > 65535 {
+            return Err(invalid("PPM max value has to be between 1 and 65535"));
+        }
+        let count = w.checked_mul(h).and_then(|n| n.checked_mul(channels)).ok_or_else(|| invalid("PPM is too big"))?;
+
+        // The size in the header could be anything, so memory is only reserved once the file is known to be big enough
+        let mut samples: Vec<usize> = Vec::new();
+        if binary {
+            pos += 1; // the single whitespace after the header
+            let size = if max < 256 { 1 } else { 2 };
+            let needed = count.checked_mul(size).ok_or_else(|| invalid("PPM is too big"))?;
+            let data = bytes.get(pos..).filter(|d| d.len() >= needed).ok_or_else(|| invalid("PPM pixels are cut short"))?;
+            samples.reserve_exact(count);
+            for i in 0..count {
+                samples.push(if size == 1 { data[i] as usize } else { u16::from_be_bytes([data[i * 2], data[i * 2 + 1]]) as usize });
+            }
+        }else {
+            // Every number takes at least a byte
+            if count > bytes.len().saturating_sub(pos) {
+                return Err(invalid("PPM pixels are cut short"));
+            }
+            samples.reserve_exact(count);
+            for _ in 0..count {
+                samples.push(header_number(bytes, &mut pos)?);
+            }
+        }
+
+        // Scaled so max becomes 255
+        let scale = |v: usize| ((v.min(max) * 255 + max / 2) / max) as u8;
+        let buf = samples.chunks(channels).map(|px| match px {
+            [gray] => rgb(scale(*gray), scale(*gray), scale(*gray)),
+            _ => rgb(scale(px[0]), scale(px[1]), scale(px[2])),
+        }).collect();
+        Ok(Image::new(buf, w, h))
+    }
+}
+
+fn invalid(reason: &str) -> Error {
+    Error::InvalidImage(reason.to_string())
+}
+
+fn rgb(r: u8, g: u8, b: u8) -> u32 {
+    ((r as u32) << 16) | ((g as u32) << 8) | b as u32
+}
+
+// The bits of the mask, scaled to 0..255
+fn channel(value: u32, mask: u32) -> u8 {
+    if mask == 0 {
+        return 0;
+    }
+    let shift = mask.trailing_zeros();
+    let max = (mask >> shift) as u64;
+    let v = ((value & mask) >> shift) as u64;
+    ((v * 255 + max / 2) / max) as u8
+}
+
+// The next number in a PPM header, skipping whitespace and comments (# until the end of the line)
+fn header_number(bytes: &[u8], pos: &mut usize) -> Result<usize, Error> {
+    loop {
+        match bytes.get(*pos) {
+            Some(b'#') => while bytes.get(*pos).is_some_and(|b| *b != b'\n') { *pos += 1 },
+            Some(b) if b.is_ascii_whitespace() => *pos += 1,
+            _ => break,
+        }
+    }
+    let start = *pos;
+    while bytes.get(*pos).is_some_and(|b| b.is_ascii_digit()) {
+        *pos += 1;
+    }
+    std::str::from_utf8(&bytes[start..*pos]).ok()
+        .and_then(|n| n.parse().ok())
+        .ok_or_else(|| invalid("PPM is missing a number"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Ctx;
+
+    // A 24 bit BMP with the rows bottom-up, pixels given top row first
+    fn bmp_24(w: usize, h: usize, pixels: &[u32]) -> Vec<u8> {
+        let row_size = (24 * w).div_ceil(32) * 4;
+        let mut bytes = b"BM".to_vec();
+        bytes.extend(((54 + row_size * h) as u32).to_le_bytes());
+        bytes.extend([0; 4]);
+        bytes.extend(54u32.to_le_bytes()); // data offset
+        bytes.extend(40u32.to_le_bytes()); // header size
+        bytes.extend((w as i32).to_le_bytes());
+        bytes.extend((h as i32).to_le_bytes());
+        bytes.extend(1u16.to_le_bytes()); // planes
+        bytes.extend(24u16.to_le_bytes());
+        bytes.extend([0; 24]); // no compression, the rest doesn't matter
+        for y in (0..h).rev() {
+            let row: Vec<u8> = pixels[y * w..(y + 1) * w].iter().flat_map(|p| [*p as u8, (p >> 8) as u8, (p >> 16) as u8]).collect();
+            bytes.extend(&row);
+            bytes.extend(vec![0; row_size - row.len()]);
+        }
+        bytes
+    }
+
+    fn assert_invalid(result: Result<Image, Error>) {
+        assert!(matches!(result, Err(Error::InvalidImage(_))), "expected InvalidImage, got {:?}", result.map(|i| (i.w, i.h)));
+    }
+
+    #[test]
+    fn png_round_trip() {
+        let mut ctx = Ctx::headless(3, 2);
+        ctx.clear_rect(0x102030);
+        ctx.draw_pixel(0, 0, 0xff0800).unwrap(); // bottom-left, the last row of the buffer
+        let image = Image::from_bytes(&ctx.to_png_bytes().unwrap()).unwrap();
+        assert_eq!((image.w, image.h), (3, 2));
+        assert_eq!(image.buf, ctx.buf);
+        assert!(image.alpha.is_none());
+    }
+
+    #[test]
+    fn bmp_round_trip() {
+        let pixels = [0xff0000, 0x00ff00, 0x0000ff, 0xffffff, 0x000000, 0x123456];
+        let image = Image::from_bytes(&bmp_24(3, 2, &pixels)).unwrap();
+        assert_eq!((image.w, image.h), (3, 2));
+        assert_eq!(image.buf, pixels);
+    }
+
+    #[test]
+    fn ppm_binary_and_plain() {
+        let mut p6 = b"P6\n# comment\n2 1\n255\n".to_vec();
+        p6.extend([255, 0, 0, 0, 128, 255]);
+        assert_eq!(Image::from_bytes(&p6).unwrap().buf, [0xff0000, 0x0080ff]);
+
+        let p2 = b"P2 2 1 15\n0 15\n";
+        assert_eq!(Image::from_bytes(p2).unwrap().buf, [0x000000, 0xffffff]);
+        let p3 = b"P3 1 1 255\n1 2 3\n";
+        assert_eq!(Image::from_bytes(p3).unwrap().buf, [0x010203]);
+    }
+
+    #[test]
+    fn ppm_oversized_header() {
+        // Used to try to reserve 240 GB before looking at the pixels
+        assert_invalid(Image::from_bytes(b"P6 100000 100000 255\n..."));
+        assert_invalid(Image::from_bytes(b"P3 100000 100000 255\n1 2 3"));
+        assert_invalid(Image::from_bytes(b"P5 99999999999999999999 1 255\n"));
+        assert_invalid(Image::from_bytes(b"P6 4294967296 4294967296 255\n"));
+    }
+
+    #[test]
+    fn ppm_cut_short() {
+        assert_invalid(Image::from_bytes(b"P6 2 2 255\n\x00\x00\x00"));
+        assert_invalid(Image::from_bytes(b"P3 2 1 255\n1 2 3 4 5"));
+        assert_invalid(Image::from_bytes(b"P6 2"));
+        assert_invalid(Image::from_bytes(b"P6 2 2 0\n"));
+    }
+
+    #[test]
+    fn bmp_oversized_header() {
+        let mut bytes = bmp_24(1, 1, &[0]);
+        bytes[18..22].copy_from_slice(&0x7fffffffi32.to_le_bytes());
+        bytes[22..26].copy_from_slice(&0x7fffffffi32.to_le_bytes());
+        assert_invalid(Image::from_bytes(&bytes));
+        // 0 bits per pixel made the rows empty, so the size check passed
+        bytes[28..30].copy_from_slice(&0u16.to_le_bytes());
+        assert_invalid(Image::from_bytes(&bytes));
+    }
+
+    #[test]
+    fn bmp_cut_short() {
+        let bytes = bmp_24(4, 4, &[0; 16]);
+        for len in [0, 2, 20, 40, 54, bytes.len() - 1] {
+            assert_invalid(Image::from_bytes(&bytes[..len]));
+        }
+    }
+}
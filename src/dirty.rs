@@ -0,0 +1,84 @@
+// Keeping track of which parts of the buffer changed since they were last shown, so the window (or whatever
+// shows the buffer) can skip the work when nothing changed, and callers can see what did.
+// Every pixel that is drawn goes through blend_pixel, which adds it here.
+
+/// A rectangle of the buffer that changed. y counts rows from the top, like the buffer itself
+/// (whatever the origin of the ctx is).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DirtyRect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+// Pixels this close to the last rect make it bigger instead of starting a new one
+const NEAR: usize = 16;
+// With more rects than this they are all merged into one
+const MAX_RECTS: usize = 32;
+
+#[derive(Clone, Default)]
+pub(crate) struct Dirty {
+    rects: Vec<[usize; 4]>, // [x0, row0, x1, row1], inclusive
+}
+
+impl Dirty {
+    pub fn add(&mut self, x: usize, row: usize) {
+        // Things are drawn a pixel at a time, and the next pixel is almost always next to the last one
+        if let Some(last) = self.rects.last_mut() {
+            if x + NEAR >= last[0] && x <= last[2] + NEAR && row + NEAR >= last[1] && row <= last[3] + NEAR {
+                *last = [last[0].min(x), last[1].min(row), last[2].max(x), last[3].max(row)];
+                return;
+            }
+        }
+        if self.rects.iter().any(|r| x >= r[0] && x <= r[2] && row >= r[1] && row <= r[3]) {
+            return;
+        }
+        if self.rects.len() >= MAX_RECTS {
+            let all = self.rects.iter().fold([x, row, x, row], |a, r| union(&a, r));
+            self.rects = vec![all];
+        }else {
+            self.rects.push([x, row, x, row]);
+        }
+    }
+
+    pub fn all(&mut self, width: usize, height: usize) {
+        self.rects.clear();
+        if width > 0 && height > 0 {
+            self.rects.push([0, 0, width - 1, height - 1]);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.rects.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rects.is_empty()
+    }
+
+    /// The rects, with the ones that overlap merged together
+    pub fn rects(&self) -> Vec<DirtyRect> {
+        let mut rects = self.rects.clone();
+        let mut merged = true;
+        while merged {
+            merged = false;
+            'outer: for i in 0..rects.len() {
+                for j in i + 1..rects.len() {
+                    let (a, b) = (rects[i], rects[j]);
+                    if a[0] <= b[2] && b[0] <= a[2] && a[1] <= b[3] && b[1] <= a[3] {
+                        rects[i] = union(&a, &b);
+                        rects.swap_remove(j);
+                        merged = true;
+                        break 'outer;
+                    }
+                }
+            }
+        }
+        rects.iter().map(|r| DirtyRect { x: r[0], y: r[1], width: r[2] - r[0] + 1, height: r[3] - r[1] + 1 }).collect()
+    }
+}
+
+fn union(a: &[usize; 4], b: &[usize; 4]) -> [usize; 4] {
+    [a[0].min(b[0]), a[1].min(b[1]), a[2].max(b[2]), a[3].max(b[3])]
+}
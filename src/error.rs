@@ -0,0 +1,85 @@
+// The errors this crate can give back, so nothing has to panic when something is a bit off.
+
+use std::fmt;
+
+#[derive(Debug)]
+pub enum Error {
+    /// Tried to draw at (x, y), which is outside of the width x height screen
+    OutOfBounds { x: isize, y: isize, width: usize, height: usize },
+    /// A graph can't be drawn between min and max, e.g. min isn't smaller than max or one of them is NaN
+    InvalidGraphRange { min: f32, max: f32 },
+    /// The axes are offset pixels in from the edges, which leaves no room for the graph on a screen that is
+    /// only size pixels wide or high
+    InvalidAxisOffset { offset: usize, size: usize },
+    /// The step of draw_graph is 0, or the step of draw_axis is so small that the numbers would be drawn on
+    /// top of each other
+    InvalidStep { step: f32 },
+    /// The window couldn't be created or updated
+    Window(minifb::Error),
+    /// A file couldn't be read or written
+    Io(std::io::Error),
+    /// An image file is broken or uses something that isn't supported (or the ctx can't be saved as one,
+    /// e.g. when it's empty), with the reason
+    InvalidImage(String),
+    /// A font file is broken or isn't a TrueType/OpenType font, with the reason
+    InvalidFont(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::OutOfBounds { x, y, width, height } => {
+                write!(f, "Attempted to access ({x},{y}) when dimensions are ({width},{height})")
+            },
+            Error::InvalidGraphRange { min, max } => write!(f, "Invalid graph range from {min} to {max}"),
+            Error::InvalidAxisOffset { offset, size } => {
+                write!(f, "Axis offset {offset} leaves no room for a graph {size} pixels across")
+            },
+            Error::InvalidStep { step } => write!(f, "Invalid graph step {step}"),
+            Error::Window(e) => write!(f, "Window error: {e}"),
+            Error::Io(e) => write!(f, "IO error: {e}"),
+            Error::InvalidImage(reason) => write!(f, "Invalid image: {reason}"),
+            Error::InvalidFont(reason) => write!(f, "Invalid font: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Window(e) => Some(e),
+            Error::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<minifb::Error> for Error {
+    fn from(e: minifb::Error) -> Self {
+        Error::Window(e)
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<png::DecodingError> for Error {
+    fn from(e: png::DecodingError) -> Self {
+        match e {
+            png::DecodingError::IoError(e) => Error::Io(e),
+            e => Error::InvalidImage(e.to_string()),
+        }
+    }
+}
+
+impl From<png::EncodingError> for Error {
+    fn from(e: png::EncodingError) -> Self {
+        match e {
+            png::EncodingError::IoError(e) => Error::Io(e),
+            e => Error::InvalidImage(e.to_string()),
+        }
+    }
+}
@@ -0,0 +1,43 @@
+// Exporting a Ctx without a window, so plots can be rendered on machines with no display.
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::{Ctx, Error};
+
+impl Ctx {
+    /// Saves the buffer as a PNG file.
+    ///
+    /// The image looks exactly like it would in the window, so y=0 of `draw_pixel` is the bottom row.
+    pub fn save_png<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        let file = File::create(path)?;
+        self.write_png(BufWriter::new(file))
+    }
+
+    /// Encodes the buffer as a PNG and returns the bytes of the file.
+    pub fn to_png_bytes(&self) -> Result<Vec<u8>, Error> {
+        let mut bytes: Vec<u8> = Vec::new();
+        self.write_png(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    fn write_png<W: Write>(&self, writer: W) -> Result<(), Error> {
+        let mut encoder = png::Encoder::new(writer, self.w as u32, self.h as u32);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        // The buffer is already stored top row first (see draw_pixel), which is the order PNG wants,
+        // so the pixels only need to be unpacked from 0RGB.
+        let mut data: Vec<u8> = Vec::with_capacity(self.w * self.h * 3);
+        for pixel in &self.buf {
+            data.push((pixel >> 16) as u8);
+            data.push((pixel >> 8) as u8);
+            data.push(*pixel as u8);
+        }
+
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&data)?;
+        Ok(writer.finish()?)
+    }
+}
@@ -0,0 +1,174 @@
+// TrueType (and OpenType) fonts for text, instead of the built-in 8x8 bitmap font in text.rs.
+// Glyphs are turned into outlines, which are filled like any other shape, so they can be any size
+// and go through the transform, clip, SVG and so on like everything else.
+
+use std::path::Path as FilePath;
+use std::sync::Arc;
+
+use crate::math::Vec2D;
+use crate::path::Path;
+use crate::raster;
+use crate::svg::Paint;
+use crate::{Ctx, Error, FillRule};
+
+/// Minecraftia by Andrew Tyler, bundled for Font::minecraftia. It is not the built-in bitmap font (MICROKNIGHT_FONT in text.rs)
+const MINECRAFTIA: &[u8] = include_bytes!("../font/Minecraftia-Regular.ttf");
+
+/// A TrueType or OpenType font, used for text with `Ctx::set_font`.
+/// ```
+/// # use bml_grapher::{Ctx, Font};
+/// let mut ctx = Ctx::headless(320, 240);
+/// ctx.set_font(Some(Font::minecraftia()));
+/// ctx.set_font_size(24.0);
+/// ctx.draw_text(&[10, 200].into(), "Title", 1);
+/// ```
+#[derive(Clone)]
+pub struct Font {
+    data: Arc<Vec<u8>>, // shared, so saving the ctx state doesn't copy the font
+}
+
+impl std::fmt::Debug for Font {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Font({})", self.name().unwrap_or_default())
+    }
+}
+
+impl Font {
+    /// The Minecraftia font that comes with the crate. It looks best at 12 or 24 pixels without anti-aliasing.
+    pub fn minecraftia() -> Self {
+        Font { data: Arc::new(MINECRAFTIA.to_vec()) }
+    }
+
+    /// A font from the bytes of a .ttf or .otf file.
+    pub fn from_bytes(data: Vec<u8>) -> Result<Self, Error> {
+        ttf_parser::Face::parse(&data, 0).map_err(|e| Error::InvalidFont(e.to_string()))?;
+        Ok(Font { data: Arc::new(data) })
+    }
+
+    pub fn open<P: AsRef<FilePath>>(path: P) -> Result<Self, Error> {
+        Self::from_bytes(std::fs::read(path)?)
+    }
+
+    /// The name of the font family, if the font has one
+    pub fn name(&self) -> Option<String> {
+        let face = self.face()?;
+        face.names().into_iter()
+            .filter(|name| name.name_id == ttf_parser::name_id::FAMILY && name.is_unicode())
+            .find_map(|name| name.to_string())
+    }
+
+    pub(crate) fn has_glyph(&self, c: char) -> bool {
+        self.face().is_some_and(|face| face.glyph_index(c).is_some())
+    }
+
+    fn face(&self) -> Option<ttf_parser::Face<'_>> {
+        ttf_parser::Face::parse(&self.data, 0).ok()
+    }
+
+    /// How wide and tall a line of text is at size pixels (the height is from the top of the tallest letters
+    /// to the bottom of the lowest ones, like the line of text the font was made for).
+    pub fn measure(&self, text: &str, size: f32) -> (f32, f32) {
+        let Some(face) = self.face() else { return (0.0, 0.0) };
+        let scale = size / face.units_per_em() as f32;
+        let width: f32 = text.chars().map(|c| {
+            let glyph = face.glyph_index(c).unwrap_or(ttf_parser::GlyphId(0));
+            face.glyph_hor_advance(glyph).unwrap_or(0) as f32 * scale
+        }).sum();
+        (width, (face.ascender() - face.descender()) as f32 * scale)
+    }
+
+    // The outlines of a line of text at size pixels, with (0, 0) at the top-left and y going down.
+    // to_device places every point, so curves are flattened as finely as they end up on the screen.
+    pub(crate) fn outlines(&self, text: &str, size: f32, to_device: impl Fn(f32, f32) -> Vec2D<f32>) -> Vec<Vec<Vec2D<f32>>> {
+        let Some(face) = self.face() else { return Vec::new() };
+        let scale = size / face.units_per_em() as f32;
+        let ascender = face.ascender() as f32 * scale;
+
+        let mut builder = Outline { path: Path::new(), x: 0.0, ascender, scale, to_device: &to_device };
+        for c in text.chars() {
+            let glyph = face.glyph_index(c).unwrap_or(ttf_parser::GlyphId(0));
+            face.outline_glyph(glyph, &mut builder);
+            builder.x += face.glyph_hor_advance(glyph).unwrap_or(0) as f32 * scale;
+        }
+        builder.path.subpaths().iter().filter(|sub| sub.points.len() > 2).map(|sub| sub.points.clone()).collect()
+    }
+}
+
+// Collects the outline of glyphs into a path
+struct Outline<'a, F: Fn(f32, f32) -> Vec2D<f32>> {
+    path: Path,
+    x: f32, // where the current glyph starts
+    ascender: f32,
+    scale: f32,
+    to_device: &'a F,
+}
+
+impl<F: Fn(f32, f32) -> Vec2D<f32>> Outline<'_, F> {
+    // From font units (y going up from the baseline) to the screen
+    fn point(&self, x: f32, y: f32) -> Vec2D<f32> {
+        (self.to_device)(self.x + x * self.scale, self.ascender - y * self.scale)
+    }
+}
+
+impl<F: Fn(f32, f32) -> Vec2D<f32>> ttf_parser::OutlineBuilder for Outline<'_, F> {
+    fn move_to(&mut self, x: f32, y: f32) {
+        let p = self.point(x, y);
+        self.path.move_to(p.x, p.y);
+    }
+    fn line_to(&mut self, x: f32, y: f32) {
+        let p = self.point(x, y);
+        self.path.line_to(p.x, p.y);
+    }
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+        let (cp, p) = (self.point(x1, y1), self.point(x, y));
+        self.path.quadratic_curve_to(cp.x, cp.y, p.x, p.y);
+    }
+    fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+        let (cp1, cp2, p) = (self.point(x1, y1), self.point(x2, y2), self.point(x, y));
+        self.path.bezier_curve_to(cp1.x, cp1.y, cp2.x, cp2.y, p.x, p.y);
+    }
+    fn close(&mut self) {
+        self.path.close_path();
+    }
+}
+
+impl Ctx {
+    /// Draws text with this font from now on, None goes back to the built-in 8x8 bitmap font.
+    /// The font size is set with set_font_size, and the scale of draw_text multiplies it.
+    pub fn set_font(&mut self, font: Option<Font>) {
+        self.state.font = font;
+    }
+    pub fn get_font(&self) -> Option<&Font> {
+        self.state.font.as_ref()
+    }
+    /// The size of text in pixels (the height of the font's em square, like in css), only for fonts set with set_font.
+    /// The default is 12.
+    pub fn set_font_size(&mut self, size: f32) {
+        if size.is_finite() && size >= 0.0 {
+            self.state.font_size = size;
+        }
+    }
+    pub fn get_font_size(&self) -> f32 {
+        self.state.font_size
+    }
+
+    // draw_text with the font that was set, the outlines are filled like any other shape (smooth with anti-aliasing on)
+    pub(crate) fn draw_font_text(&mut self, font: &Font, pos: &Vec2D<usize>, text: &str, scale: usize, color: u32, alpha: f32) {
+        let size = self.state.font_size * scale as f32;
+        let transform = self.state.transform;
+        // Placed the same way as the bitmap font
+        let place = self.text_placement(pos, text, scale);
+        let (lines, advance) = self.text_lines(text, scale);
+        let mut contours = Vec::new();
+        for (i, line) in lines.iter().enumerate() {
+            let top = i as f32 * advance;
+            contours.extend(font.outlines(line, size, |x, y| transform.apply(&place(x, top + y))));
+        }
+        if self.svg.is_some() {
+            let lines: Vec<_> = contours.into_iter().map(|contour| (contour, true)).collect();
+            return self.svg_shape(&lines, Paint::Fill(FillRule::NonZero), color, alpha);
+        }
+        let pixels = raster::fill_polygons(&contours, FillRule::NonZero, self.state.anti_aliasing, (self.w, self.h));
+        self.fill_coverage(pixels, color, alpha);
+    }
+}
@@ -0,0 +1,157 @@
+// Gradients to fill shapes with, like createLinearGradient and createRadialGradient in js.
+// The gradient is placed with the same coordinates as the shape, and goes through the transform with it.
+
+use crate::math::{lerp, Vec2D};
+use crate::raster::{self, Coverage};
+use crate::record::Command;
+use crate::{Ctx, FillRule, Rgba};
+
+/// Colors that change smoothly along a line (linear) or between two circles (radial).
+/// Past the first and last color stops the color stays the same.
+/// ```
+/// # use bml_grapher::{Ctx, Gradient, Rgba};
+/// let mut ctx = Ctx::headless(320, 240);
+/// let mut gradient = Gradient::linear(0.0, 0.0, 0.0, 100.0);
+/// gradient.add_color_stop(0.0, &Rgba::from_hex(0x4328ed, 255));
+/// gradient.add_color_stop(1.0, &Rgba::from_hex(0x4328ed, 0)); // fades out at the top
+/// ctx.fill_rect_gradient(&[20, 0].into(), 200, 100, &gradient);
+/// ```
+#[derive(Clone, Debug)]
+pub struct Gradient {
+    pub(crate) kind: GradientKind,
+    pub(crate) stops: Vec<(f32, u32, f32)>, // (offset, color, alpha), sorted by offset
+}
+
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum GradientKind {
+    Linear { x0: f32, y0: f32, x1: f32, y1: f32 },
+    Radial { x0: f32, y0: f32, r0: f32, x1: f32, y1: f32, r1: f32 },
+}
+
+impl Gradient {
+    /// A gradient from (x0, y0) (offset 0) to (x1, y1) (offset 1).
+    pub fn linear(x0: f32, y0: f32, x1: f32, y1: f32) -> Self {
+        Gradient { kind: GradientKind::Linear { x0, y0, x1, y1 }, stops: Vec::new() }
+    }
+    /// A gradient from the circle at (x0, y0) with radius r0 (offset 0) to the one at (x1, y1) with radius r1 (offset 1).
+    /// Usually both circles have the same center and r0 is 0.
+    pub fn radial(x0: f32, y0: f32, r0: f32, x1: f32, y1: f32, r1: f32) -> Self {
+        Gradient { kind: GradientKind::Radial { x0, y0, r0, x1, y1, r1 }, stops: Vec::new() }
+    }
+
+    /// Adds a color at offset, from 0.0 (the start) to 1.0 (the end). Offsets outside of that are ignored.
+    /// Stops at the same offset make a sharp edge, in the order they were added.
+    pub fn add_color_stop(&mut self, offset: f32, color: &Rgba) {
+        if !(0.0..=1.0).contains(&offset) {
+            return;
+        }
+        let i = self.stops.iter().position(|stop| stop.0 > offset).unwrap_or(self.stops.len());
+        self.stops.insert(i, (offset, color.to_hex(), color.alpha()));
+    }
+
+    /// The color and alpha at p (in the coordinates the gradient was made with), None where there is no color
+    /// (no stops, or outside of a radial gradient's cone).
+    pub fn color_at(&self, p: &Vec2D<f32>) -> Option<(u32, f32)> {
+        let t = self.offset_at(p)?;
+        let (first, last) = (self.stops.first()?, self.stops.last()?);
+        if t <= first.0 {
+            return Some((first.1, first.2));
+        }
+        if t >= last.0 {
+            return Some((last.1, last.2));
+        }
+        let i = self.stops.iter().rposition(|stop| stop.0 <= t)?;
+        let ((o0, c0, a0), (o1, c1, a1)) = (self.stops[i], self.stops[i + 1]);
+        let f = (t - o0) / (o1 - o0);
+
+        // Mixed with the alpha multiplied in, so fading to a see-through color doesn't go dark on the way
+        let alpha = lerp(a0, a1, f);
+        if alpha <= 0.0 {
+            return Some((0, 0.0));
+        }
+        let channel = |shift: u32| {
+            let (v0, v1) = (((c0 >> shift) & 0xff) as f32, ((c1 >> shift) & 0xff) as f32);
+            ((lerp(v0 * a0, v1 * a1, f) / alpha).round() as u32).min(255) << shift
+        };
+        Some((channel(16) | channel(8) | channel(0), alpha))
+    }
+
+    // How far along the gradient p is (not limited to 0..1)
+    fn offset_at(&self, p: &Vec2D<f32>) -> Option<f32> {
+        match self.kind {
+            GradientKind::Linear { x0, y0, x1, y1 } => {
+                let (dx, dy) = (x1 - x0, y1 - y0);
+                let length = dx * dx + dy * dy;
+                if length == 0.0 {
+                    return None; // nothing is painted, like in js
+                }
+                Some(((p.x - x0) * dx + (p.y - y0) * dy) / length)
+            },
+            GradientKind::Radial { x0, y0, r0, x1, y1, r1 } => {
+                // The largest t where p is on the circle between the two (with a radius that isn't negative),
+                // (p - c(t))^2 = r(t)^2 with c(t) and r(t) going from the first circle to the second
+                let (cx, cy, dr) = (x1 - x0, y1 - y0, r1 - r0);
+                let (px, py) = (p.x - x0, p.y - y0);
+                let a = cx * cx + cy * cy - dr * dr;
+                let b = px * cx + py * cy + r0 * dr;
+                let c = px * px + py * py - r0 * r0;
+                let radius_ok = |t: f32| r0 + t * dr >= 0.0;
+                if a == 0.0 {
+                    if b == 0.0 {
+                        return None;
+                    }
+                    let t = c / (2.0 * b);
+                    return radius_ok(t).then_some(t);
+                }
+                let discriminant = b * b - a * c;
+                if discriminant < 0.0 {
+                    return None;
+                }
+                let root = discriminant.sqrt();
+                let (t0, t1) = ((b + root) / a, (b - root) / a);
+                [t0.max(t1), t0.min(t1)].into_iter().find(|t| radius_ok(*t))
+            },
+        }
+    }
+}
+
+impl Ctx {
+    /// Same as fill_rect, but with a gradient instead of one color.
+    pub fn fill_rect_gradient(&mut self, pos: &Vec2D<usize>, width: usize, height: usize, gradient: &Gradient) {
+        let corners = Self::rect_corners(&pos.f32(), width as f32, height as f32);
+        let anti_aliasing = self.rect_anti_aliasing();
+        self.fill_shapes_gradient(&[self.to_device(&corners)], FillRule::NonZero, anti_aliasing, gradient);
+    }
+
+    /// Same as fill_polygon, but with a gradient instead of one color.
+    pub fn fill_polygon_gradient(&mut self, points: &[Vec2D<f32>], rule: FillRule, gradient: &Gradient) {
+        self.fill_shapes_gradient(&[self.to_device(points)], rule, self.state.anti_aliasing, gradient);
+    }
+
+    /// Fills the current path with a gradient (using the nonzero rule). The gradient is placed with the
+    /// transform that's used when filling, not the one the path was made with (like in js).
+    pub fn fill_gradient(&mut self, gradient: &Gradient) {
+        self.fill_gradient_with_rule(FillRule::NonZero, gradient);
+    }
+
+    pub fn fill_gradient_with_rule(&mut self, rule: FillRule, gradient: &Gradient) {
+        let contours: Vec<Vec<Vec2D<f32>>> = self.path.subpaths().iter().map(|sub| sub.points.clone()).collect();
+        self.fill_shapes_gradient(&contours, rule, self.state.anti_aliasing, gradient);
+    }
+
+    // Same as fill_shapes, every pixel gets its color from the gradient
+    pub(crate) fn fill_shapes_gradient(&mut self, contours: &[Vec<Vec2D<f32>>], rule: FillRule, anti_aliasing: bool, gradient: &Gradient) {
+        self.record(|ctx| Command::Gradient {
+            contours: contours.to_vec(), rule, anti_aliasing, gradient: gradient.clone(), state: ctx.state.clone(),
+        });
+        if self.svg.is_some() {
+            return self.svg_gradient_shape(contours, rule, gradient);
+        }
+        // The pixels are on the screen, the gradient is in the coordinates before the transform
+        let Some(inverse) = self.state.transform.invert() else { return };
+        let pixels: Coverage = raster::fill_polygons(contours, rule, anti_aliasing, (self.w, self.h));
+        self.paint_coverage(pixels, |x, y| {
+            gradient.color_at(&inverse.apply(&Vec2D::new(x as f32, y as f32))).unwrap_or((0, 0.0))
+        });
+    }
+}
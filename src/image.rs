@@ -0,0 +1,236 @@
+// Drawing pixel buffers (images, other contexts) onto a Ctx, like drawImage in js.
+// Loading images from files is in decode.rs.
+
+use crate::math::Vec2D;
+use crate::{Ctx, Origin};
+
+/// Anything that can be drawn with `Ctx::draw_image`. The pixels are 0RGB like in Ctx, the top row first.
+pub trait ImageSource {
+    fn width(&self) -> usize;
+    fn height(&self) -> usize;
+    /// The color of the pixel in column x, row y (counting from the top)
+    fn pixel(&self, x: usize, y: usize) -> u32;
+    /// How opaque the pixel is, from 0.0 to 1.0. Everything is opaque unless this is overridden.
+    fn alpha(&self, _x: usize, _y: usize) -> f32 {
+        1.0
+    }
+}
+
+/// A pixel buffer from somewhere else (e.g. a minifb buffer), to draw it without copying.
+pub struct PixelBuffer<'a> {
+    pub pixels: &'a [u32],
+    pub width: usize,
+    pub height: usize,
+}
+impl<'a> PixelBuffer<'a> {
+    pub fn new(pixels: &'a [u32], width: usize, height: usize) -> Self {
+        PixelBuffer { pixels, width, height }
+    }
+}
+impl ImageSource for PixelBuffer<'_> {
+    fn width(&self) -> usize { self.width }
+    fn height(&self) -> usize { self.height }
+    fn pixel(&self, x: usize, y: usize) -> u32 {
+        self.pixels.get(y * self.width + x).copied().unwrap_or(0)
+    }
+}
+
+/// An image in the same layout as Ctx (0RGB, top row first), e.g. loaded with `Image::open`.
+#[derive(Clone, Debug)]
+pub struct Image {
+    pub buf: Vec<u32>,
+    pub w: usize,
+    pub h: usize,
+    /// How opaque every pixel is (0 to 255), only for images that have transparency
+    pub alpha: Option<Vec<u8>>,
+}
+impl Image {
+    pub fn new(buf: Vec<u32>, w: usize, h: usize) -> Self {
+        Image { buf, w, h, alpha: None }
+    }
+}
+// The fields can be set to anything, so pixels missing from buf are black and missing alphas opaque (like PixelBuffer)
+impl ImageSource for Image {
+    fn width(&self) -> usize { self.w }
+    fn height(&self) -> usize { self.h }
+    fn pixel(&self, x: usize, y: usize) -> u32 {
+        self.buf.get(y * self.w + x).copied().unwrap_or(0)
+    }
+    fn alpha(&self, x: usize, y: usize) -> f32 {
+        match &self.alpha {
+            Some(alpha) => alpha.get(y * self.w + x).map_or(1.0, |a| *a as f32 / 255.0),
+            None => 1.0,
+        }
+    }
+}
+
+// A ctx can be drawn onto another one, e.g. to cache something that's slow to draw
+impl ImageSource for Ctx {
+    fn width(&self) -> usize { self.w }
+    fn height(&self) -> usize { self.h }
+    fn pixel(&self, x: usize, y: usize) -> u32 {
+        self.buf[y * self.w + x]
+    }
+    fn alpha(&self, x: usize, y: usize) -> f32 {
+        match &self.alpha {
+            Some(alpha) => alpha[y * self.w + x] as f32 / 255.0,
+            None => 1.0,
+        }
+    }
+}
+
+/// How an image is sampled when it's scaled (or rotated).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImageFilter {
+    /// The closest pixel, blocky but exact. This is the default.
+    Nearest,
+    /// Blends the 4 closest pixels, smooth (like imageSmoothingEnabled in js).
+    Bilinear,
+}
+
+impl Ctx {
+    pub fn set_image_filter(&mut self, filter: ImageFilter) {
+        self.state.image_filter = filter;
+    }
+    pub fn get_image_filter(&self) -> ImageFilter {
+        self.state.image_filter
+    }
+    /// Pixels of this color in images are left out (transparent), None to draw every pixel.
+    pub fn set_color_key(&mut self, color: Option<u32>) {
+        self.state.color_key = color;
+    }
+
+    /// Draws the whole image at its own size. pos is the corner closest to the origin, like in fill_rect,
+    /// and the image is always the right way up.
+    pub fn draw_image<I: ImageSource + ?Sized>(&mut self, src: &I, pos: &Vec2D<f32>) {
+        let size = [src.width() as f32, src.height() as f32];
+        self.draw_image_part(src, [0, 0, src.width(), src.height()], pos, size);
+    }
+
+    /// Draws the whole image stretched to width x height.
+    pub fn draw_image_scaled<I: ImageSource + ?Sized>(&mut self, src: &I, pos: &Vec2D<f32>, width: f32, height: f32) {
+        self.draw_image_part(src, [0, 0, src.width(), src.height()], pos, [width, height]);
+    }
+
+    /// Draws part of the image, source = [x, y, width, height] in the image (y counting from the top),
+    /// stretched to size = [width, height].
+    pub fn draw_image_part<I: ImageSource + ?Sized>(&mut self, src: &I, source: [usize; 4], pos: &Vec2D<f32>, size: [f32; 2]) {
+        // Only the part of the source rect that's actually in the image
+        let [sx, sy] = [source[0].min(src.width()), source[1].min(src.height())];
+        let [sw, sh] = [source[2].min(src.width() - sx), source[3].min(src.height() - sy)];
+        let [dw, dh] = size;
+        let Some(inverse) = self.state.transform.invert() else { return };
+        if sw == 0 || sh == 0 || dw == 0.0 || dh == 0.0 {
+            return;
+        }
+        self.record_image(src, [sx, sy, sw, sh], pos, size);
+        // Images aren't part of SVGs
+        if self.w == 0 || self.h == 0 || self.svg.is_some() {
+            return;
+        }
+
+        // Only go over the pixels the image can end up on
+        let corners = self.to_device(&[
+            pos.clone(), Vec2D::new(pos.x + dw, pos.y),
+            Vec2D::new(pos.x + dw, pos.y + dh), Vec2D::new(pos.x, pos.y + dh),
+        ]);
+        let [mut min_x, mut min_y, mut max_x, mut max_y] = [f32::MAX, f32::MAX, f32::MIN, f32::MIN];
+        for c in &corners {
+            min_x = min_x.min(c.x); max_x = max_x.max(c.x);
+            min_y = min_y.min(c.y); max_y = max_y.max(c.y);
+        }
+        if max_x < 0.0 || max_y < 0.0 || !(min_x.is_finite() && min_y.is_finite() && max_x.is_finite() && max_y.is_finite()) {
+            return;
+        }
+        let (w, h) = (self.w, self.h);
+        let [x0, x1] = [min_x.floor(), max_x.ceil()].map(|n| (n.max(0.0) as usize).min(w - 1));
+        let [y0, y1] = [min_y.floor(), max_y.ceil()].map(|n| (n.max(0.0) as usize).min(h - 1));
+        let (filter, key, origin) = (self.state.image_filter, self.state.color_key, self.state.origin);
+
+        for py in y0..=y1 {
+            for px in x0..=x1 {
+                // The middle of the pixel, in the coordinates the image was placed with
+                let p = inverse.apply(&Vec2D::new(px as f32 + 0.5, py as f32 + 0.5));
+                let u = (p.x - pos.x) / dw * sw as f32;
+                // The image's rows go down from the top, so with a bottom-left origin they start at the top edge
+                let v = match origin {
+                    Origin::BottomLeft => (pos.y + dh - p.y) / dh * sh as f32,
+                    Origin::TopLeft => (p.y - pos.y) / dh * sh as f32,
+                };
+                if !(u >= 0.0 && v >= 0.0 && u < sw as f32 && v < sh as f32) {
+                    continue;
+                }
+
+                let (color, alpha) = match filter {
+                    ImageFilter::Nearest => sample(src, sx + u as usize, sy + v as usize, key),
+                    ImageFilter::Bilinear => bilinear(src, [sx, sy, sw, sh], u - 0.5, v - 0.5, key),
+                };
+                if alpha > 0.0 {
+                    let _ = self.blend_pixel(px, py, color, alpha);
+                }
+            }
+        }
+    }
+}
+
+// A pixel with its alpha, pixels with the color key are see-through
+fn sample<I: ImageSource + ?Sized>(src: &I, x: usize, y: usize, key: Option<u32>) -> (u32, f32) {
+    let color = src.pixel(x, y) & 0xffffff;
+    if key == Some(color) {
+        (color, 0.0)
+    }else {
+        (color, src.alpha(x, y))
+    }
+}
+
+// Blends the 4 pixels around (u, v), which is relative to the source rect. Pixels outside of it are
+// replaced by the closest one at the edge, so the edges don't fade out.
+fn bilinear<I: ImageSource + ?Sized>(src: &I, [sx, sy, sw, sh]: [usize; 4], u: f32, v: f32, key: Option<u32>) -> (u32, f32) {
+    let (u, v) = (u.clamp(0.0, (sw - 1) as f32), v.clamp(0.0, (sh - 1) as f32));
+    let (x0, y0) = (u.floor() as usize, v.floor() as usize);
+    let (x1, y1) = ((x0 + 1).min(sw - 1), (y0 + 1).min(sh - 1));
+    let (fx, fy) = (u - x0 as f32, v - y0 as f32);
+
+    // Weighted by alpha too, so see-through pixels don't bleed their color in
+    let mut channels = [0.0f32; 3];
+    let mut alpha = 0.0;
+    for (x, y, weight) in [(x0, y0, (1.0 - fx) * (1.0 - fy)), (x1, y0, fx * (1.0 - fy)), (x0, y1, (1.0 - fx) * fy), (x1, y1, fx * fy)] {
+        let (color, a) = sample(src, sx + x, sy + y, key);
+        let w = weight * a;
+        for (i, shift) in [16, 8, 0].into_iter().enumerate() {
+            channels[i] += ((color >> shift) & 0xff) as f32 * w;
+        }
+        alpha += w;
+    }
+    if alpha <= 0.0 {
+        return (0, 0.0);
+    }
+    let [r, g, b] = channels.map(|c| ((c / alpha).round() as u32).min(255));
+    ((r << 16) | (g << 8) | b, alpha)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn image_with_missing_pixels() {
+        let mut ctx = Ctx::headless(20, 20);
+        ctx.clear_rect(0xffffff);
+        // Used to index past the end of buf
+        ctx.draw_image(&Image::new(vec![], 10, 10), &Vec2D::new(0.0, 0.0));
+        ctx.set_image_filter(ImageFilter::Bilinear);
+        ctx.draw_image_scaled(&Image::new(vec![0xff0000; 3], 2, 2), &Vec2D::new(10.0, 10.0), 5.0, 5.0);
+
+        let mut image = Image::new(vec![0x00ff00; 4], 2, 2);
+        image.alpha = Some(vec![0]);
+        assert_eq!(image.alpha(0, 0), 0.0);
+        assert_eq!(image.alpha(1, 1), 1.0);
+        assert_eq!(image.pixel(1, 1), 0x00ff00);
+        assert_eq!(Image::new(vec![], 10, 10).pixel(5, 5), 0);
+
+        // The missing pixels are drawn black
+        assert_eq!(ctx.buf[19 * 20], 0x000000);
+        assert_eq!(ctx.buf[19 * 20 + 15], 0xffffff);
+    }
+}
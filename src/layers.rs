@@ -0,0 +1,150 @@
+// A stack of transparent contexts that are drawn on separately and combined into one picture, so things that
+// don't change (the background, the grid, a slow function) only have to be drawn once.
+// Only the parts of the layers that changed (see dirty.rs) are combined again.
+
+use crate::{blend, Ctx, DirtyRect};
+
+/// The layers of `Layers`, from the bottom up.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Layer {
+    Background,
+    Grid,
+    Data,
+    Overlay,
+}
+impl Layer {
+    /// Every layer, from the bottom up
+    pub const ALL: [Layer; 4] = [Layer::Background, Layer::Grid, Layer::Data, Layer::Overlay];
+}
+
+/// A transparent Ctx for every `Layer`, drawn on top of each other (on black) by `composite`.
+/// ```
+/// # use bml_grapher::{Layer, Layers};
+/// let mut layers = Layers::new(320, 240);
+/// layers.get(Layer::Background).clear_rect(0xffffff);
+/// layers.get(Layer::Data).draw_line(&[10,10].into(), &[300,200].into(), 0xff0800).unwrap();
+///
+/// // Only the marker is drawn again every frame
+/// layers.get(Layer::Overlay).clear_transparent();
+/// layers.get(Layer::Overlay).fill_circle(&[50,50].into(), 4, 0x4328ed);
+/// let frame = layers.composite();
+/// ```
+pub struct Layers {
+    layers: Vec<Ctx>,
+    visible: [bool; 4],
+    output: Ctx,
+}
+
+impl Layers {
+    pub fn new(width: usize, height: usize) -> Self {
+        Layers {
+            layers: Layer::ALL.iter().map(|_| Ctx::transparent(width, height)).collect(),
+            visible: [true; 4],
+            output: Ctx::headless(width, height),
+        }
+    }
+
+    /// The ctx of a layer, to draw on it
+    pub fn get(&mut self, layer: Layer) -> &mut Ctx {
+        &mut self.layers[layer as usize]
+    }
+
+    /// Hides or shows a layer, e.g. to turn the grid off.
+    pub fn set_visible(&mut self, layer: Layer, visible: bool) {
+        if self.visible[layer as usize] != visible {
+            self.visible[layer as usize] = visible;
+            // Everything it covers has to be combined again
+            let (w, h) = (self.output.w, self.output.h);
+            self.layers[layer as usize].dirty.all(w, h);
+        }
+    }
+    pub fn is_visible(&self, layer: Layer) -> bool {
+        self.visible[layer as usize]
+    }
+
+    /// Combines the layers into one ctx, which can be shown (SnorfWindow::update_layers does this) or saved.
+    /// Only the parts of the layers that were drawn on since the last time are combined again.
+    pub fn composite(&mut self) -> &mut Ctx {
+        let rects: Vec<DirtyRect> = self.layers.iter().flat_map(|layer| layer.dirty_rects()).collect();
+        for rect in rects {
+            for row in rect.y..rect.y + rect.height {
+                for x in rect.x..rect.x + rect.width {
+                    let idx = row * self.output.w + x;
+                    let mut color = 0;
+                    for (layer, _) in self.layers.iter().zip(self.visible).filter(|(_, visible)| *visible) {
+                        let alpha = layer.alpha.as_ref().map_or(255, |alpha| alpha[idx]);
+                        color = match alpha {
+                            0 => color,
+                            255 => layer.buf[idx],
+                            a => blend(color, layer.buf[idx], a as f32 / 255.0),
+                        };
+                    }
+                    self.output.buf[idx] = color;
+                    self.output.dirty.add(x, row);
+                }
+            }
+        }
+        for layer in &mut self.layers {
+            layer.clear_dirty();
+        }
+        &mut self.output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn composite_blends_layers() {
+        let mut layers = Layers::new(10, 10);
+        layers.get(Layer::Background).clear_rect(0xffffff);
+        let overlay = layers.get(Layer::Overlay);
+        overlay.set_global_alpha(0.5);
+        overlay.fill_rect(&[0, 0].into(), 5, 10, 0xff0000);
+
+        let frame = layers.composite();
+        assert_eq!(frame.buf[0], 0xff7f7f);
+        assert_eq!(frame.buf[9], 0xffffff);
+
+        // Half covered twice is a bit more than three quarters covered (the alpha is kept in bytes),
+        // and the layer on top counts for more
+        let grid = layers.get(Layer::Grid);
+        grid.set_global_alpha(0.5);
+        grid.fill_rect(&[0, 0].into(), 10, 10, 0x0000ff);
+        grid.fill_rect(&[0, 0].into(), 10, 10, 0x0000ff);
+        let frame = layers.composite();
+        assert_eq!(frame.buf[9], 0x3f3fff);
+        assert_eq!(frame.buf[0], 0x9f1f7f);
+    }
+
+    #[test]
+    fn composite_skips_hidden_layers() {
+        let mut layers = Layers::new(10, 10);
+        layers.get(Layer::Background).clear_rect(0xffffff);
+        layers.get(Layer::Data).fill_rect(&[2, 2].into(), 2, 2, 0xff0800);
+        assert_eq!(layers.composite().buf.iter().filter(|&&p| p == 0xff0800).count(), 4);
+
+        layers.set_visible(Layer::Data, false);
+        assert!(layers.composite().buf.iter().all(|&p| p == 0xffffff));
+        layers.set_visible(Layer::Data, true);
+        assert_eq!(layers.composite().buf.iter().filter(|&&p| p == 0xff0800).count(), 4);
+    }
+
+    #[test]
+    fn composite_only_redoes_what_changed() {
+        let mut layers = Layers::new(10, 10);
+        layers.get(Layer::Background).clear_rect(0xffffff);
+        layers.composite();
+        assert!(layers.composite().dirty_rects().iter().all(|rect| rect.width * rect.height == 100));
+
+        // Whatever is in the output where nothing changed is left alone
+        layers.output.clear_dirty();
+        layers.output.buf.fill(0x1be81b);
+        layers.get(Layer::Overlay).fill_rect(&[0, 0].into(), 1, 1, 0x4328ed);
+        let frame = layers.composite();
+        assert_eq!(frame.buf[90], 0x4328ed);
+        assert_eq!(frame.buf.iter().filter(|&&p| p == 0x1be81b).count(), 99);
+        assert_eq!(frame.dirty_rects().len(), 1);
+    }
+}
@@ -1,23 +1,52 @@
 // TODO: 1) Basic line drawing [done], 2) Text via fonts, see how minifb did it.
-// TODO: 3) Line thickness Add as ctx property like js. [done,kinda] 
+// TODO: 3) Line thickness Add as ctx property like js. [done] 
 
 
 pub mod math;
 pub mod text;
+mod clip;
+mod decode;
+mod dirty;
+mod error;
+mod font;
+mod gradient;
+mod export;
+mod image;
+mod layers;
+mod path;
+mod raster;
+mod record;
+mod stroke;
+mod svg;
 
 use minifb::{Key, Window, WindowOptions};
-use math::{invLerp, lerp, Vec2D};
+use clip::Clip;
+use dirty::Dirty;
+use math::{invLerp, lerp, Transform, Vec2D};
+use path::Path;
+use raster::Coverage;
+use record::Command;
+use stroke::StrokeStyle;
+use svg::{Paint, Svg};
 use text::Text;
 
-use std::f32::consts::PI;
+pub use dirty::DirtyRect;
+pub use error::Error;
+pub use font::Font;
+pub use gradient::Gradient;
+pub use image::{Image, ImageFilter, ImageSource, PixelBuffer};
+pub use layers::{Layer, Layers};
+pub use raster::FillRule;
+pub use record::DisplayList;
+pub use stroke::{LineCap, LineJoin};
+pub use svg::Target;
+pub use text::{TextAlign, TextBaseline};
 
 
 
 /// The Window
 pub struct SnorfWindow {
     window: Window,
-    w : usize, // window width
-    h : usize, // window height
 }
 impl SnorfWindow {
     /// Create a SnorfWindow
@@ -25,8 +54,11 @@ impl SnorfWindow {
     /// Common settings:
     /// borderless, title, resize, scale.
     /// 
+    /// Panics if the window can't be created, use try_new to get an error instead.
+    /// 
     /// Steps to creating a SnorfWindow:
-    /// ```
+    /// ```no_run
+    /// # use bml_grapher::SnorfWindow;
     /// let mut window =  SnorfWindow::new("Grapher", 480,540, None);
     /// let mut ctx = window.get_context();
     /// // 3) <Optional change settings>: window.window.set_...
@@ -40,17 +72,13 @@ impl SnorfWindow {
     /// }
     /// ```
     pub fn new(name: &str, w: usize, h: usize, options: Option<WindowOptions>) -> Self {
-        if let Some(_options) = options {
-            SnorfWindow{ 
-                window:  Window::new(name, w, h,_options).unwrap(),
-                w, h
-            }                        
-        }else {
-            SnorfWindow{
-                window: Window::new(name, w, h, WindowOptions::default() ).unwrap(),
-                w,h
-            }
-        }
+        Self::try_new(name, w, h, options).unwrap()
+    }
+
+    /// Same as new, but gives back an error if the window can't be created (e.g. there's no display).
+    pub fn try_new(name: &str, w: usize, h: usize, options: Option<WindowOptions>) -> Result<Self, Error> {
+        let window = Window::new(name, w, h, options.unwrap_or_default())?;
+        Ok(SnorfWindow { window })
     }
     //----------------------- Common settings
     pub fn set_target_fps(&mut self, fps: usize) {
@@ -64,7 +92,7 @@ impl SnorfWindow {
     //------------------------ Common other
     /// Check if the window is open
     pub fn is_open(&self) -> bool {
-        return self.window.is_open() && !self.window.is_key_down(Key::Escape)
+        self.window.is_open() && !self.window.is_key_down(Key::Escape)
     }
 
     //-------------------------------
@@ -77,169 +105,1027 @@ impl SnorfWindow {
         let (w, h) = &self.window.get_size();
         let buffer: Vec<u32> = vec![0; w*h];
 
-        return Ctx::new(buffer, *w, *h)
+        Ctx::new(buffer, *w, *h)
+    }
+
+    /// Get a stack of layers that matches the size of the window, see `Layers`
+    pub fn get_layers(&self) -> Layers {
+        let (w, h) = self.window.get_size();
+        Layers::new(w, h)
     }
 
 
     /// Update the window with the context buffer
-    pub fn update(&mut self, ctx: &Ctx) -> Result<(), minifb::Error>{
+    pub fn update(&mut self, ctx: &Ctx) -> Result<(), Error>{
         let (w, h) = self.window.get_size();
-        self.window.update_with_buffer(&ctx.buf, w, h)
+        Ok(self.window.update_with_buffer(&ctx.buf, w, h)?)
     }
-}
 
-/// Everything is drawn in respect to the bottom left of the screen
+    /// Same as update, but the buffer is only sent to the window if something was drawn since the last time,
+    /// otherwise the window just handles its events. For live plots where most frames don't change anything.
+    /// 
+    /// Afterwards nothing in the ctx is dirty anymore (see `Ctx::dirty_rects`).
+    pub fn update_dirty(&mut self, ctx: &mut Ctx) -> Result<(), Error> {
+        if ctx.dirty.is_empty() {
+            self.window.update();
+            return Ok(());
+        }
+        self.update(ctx)?;
+        ctx.clear_dirty();
+        Ok(())
+    }
+
+    /// Composites the layers and updates the window with the result, only if one of them changed.
+    pub fn update_layers(&mut self, layers: &mut Layers) -> Result<(), Error> {
+        self.update_dirty(layers.composite())
+    }
+}
 
+/// Everything is drawn in respect to the bottom left of the screen, unless the origin is changed with set_origin
 pub struct Ctx {
     buf: Vec<u32>,
     w: usize, 
     h: usize,
 
+    state: State,
+    saved: Vec<State>, // the stack for save() and restore()
+    path: Path, // the current path, see begin_path
+    svg: Option<Svg>, // the SVG being drawn, if the target is Svg (see set_target)
+    recording: Option<DisplayList>, // see begin_recording
+    dirty: Dirty, // the pixels that changed since clear_dirty
+    alpha: Option<Vec<u8>>, // how opaque every pixel is (laid out like buf), only for transparent contexts
+}
+
+// The drawing settings, which save() and restore() keep track of (like in js)
+#[derive(Clone)]
+struct State {
     thickness: usize, // thickness of lines
+    global_alpha: f32, // opacity applied to everything that is drawn, like globalAlpha in js
+    anti_aliasing: bool, // smooth lines instead of jagged ones
+    line_cap: LineCap,
+    line_join: LineJoin,
+    miter_limit: f32,
+    transform: Transform, // from the coordinates given to the pixels on screen
+    clip: Option<Clip>, // nothing is drawn outside of it, None means the whole screen
+    line_dash: Vec<f32>, // lengths of the dashes and gaps, empty for solid lines
+    line_dash_offset: f32,
+    origin: Origin,
+    image_filter: ImageFilter, // how images are scaled
+    color_key: Option<u32>, // the color that is transparent in images
+    svg_clip: Option<String>, // id of the clip path in the SVG, the same as clip but for the Svg target
+    font: Option<Font>, // None for the built-in bitmap font
+    font_size: f32, // in pixels, only for fonts
+    text_align: TextAlign,
+    text_baseline: TextBaseline,
+    text_color: (u32, f32), // (color, alpha) of draw_text
+    text_background: Option<(u32, f32)>, // the box behind text, None for no box
+    line_spacing: usize, // empty pixels between lines of text (at scale 1)
+    replacement: char, // drawn instead of characters the font doesn't have
+}
+impl Default for State {
+    fn default() -> Self {
+        State {
+            thickness: 1, global_alpha: 1.0, anti_aliasing: false,
+            line_cap: LineCap::Butt, line_join: LineJoin::Miter, miter_limit: 10.0,
+            transform: Transform::identity(),
+            clip: None,
+            line_dash: Vec::new(), line_dash_offset: 0.0,
+            origin: Origin::BottomLeft,
+            image_filter: ImageFilter::Nearest, color_key: None,
+            svg_clip: None,
+            font: None, font_size: 12.0,
+            text_align: TextAlign::Left, text_baseline: TextBaseline::Top,
+            text_color: (0x000000, 1.0), text_background: None,
+            line_spacing: 1, replacement: '?',
+        }
+    }
+}
+
+/// Where (0, 0) is on the screen, and which way y goes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Origin {
+    /// y goes up from the bottom-left corner, like a graph. This is the default.
+    BottomLeft,
+    /// y goes down from the top-left corner, like the js canvas and most UI code.
+    TopLeft,
 }
+
 impl Ctx {
     // border_offsets: [min_xnum, max_xnum, min_ynum, max_ynum]
     pub fn new(buffer: Vec<u32>, width: usize, height: usize)-> Self {
-        Ctx{buf:buffer, w: width, h: height, thickness: 1}
+        // None of it has been shown yet
+        let mut dirty = Dirty::default();
+        dirty.all(width, height);
+        Ctx{buf:buffer, w: width, h: height, state: State::default(), saved: Vec::new(), path: Path::new(), svg: None, recording: None, dirty, alpha: None}
+    }
+
+    /// Create a black context that isn't attached to any window, for rendering without a display.
+    /// 
+    /// The result can be exported with `save_png` or `to_png_bytes`.
+    /// ```
+    /// # use bml_grapher::Ctx;
+    /// let mut ctx = Ctx::headless(320, 240);
+    /// ctx.clear_rect(0xffffff);
+    /// ctx.draw_line(&[10,10].into(), &[300,200].into(), 0xff0800).unwrap();
+    /// let png = ctx.to_png_bytes().unwrap();
+    /// ```
+    pub fn headless(width: usize, height: usize) -> Self {
+        Ctx::new(vec![0; width*height], width, height)
+    }
+
+    /// Create a context that starts out completely see-through and keeps track of how opaque every pixel is,
+    /// so it can be drawn on top of other things (with draw_image, or as one of the `Layers`).
+    pub fn transparent(width: usize, height: usize) -> Self {
+        let mut ctx = Ctx::headless(width, height);
+        ctx.alpha = Some(vec![0; width*height]);
+        ctx
+    }
+
+    /// Makes everything see-through again, on a ctx made with `Ctx::transparent` (otherwise it's cleared to black).
+    pub fn clear_transparent(&mut self) {
+        self.record(|_| Command::ClearTransparent);
+        if self.svg.is_some() {
+            return self.svg_clear(None);
+        }
+        self.buf.fill(0);
+        if let Some(alpha) = &mut self.alpha {
+            alpha.fill(0);
+        }
+        self.dirty.all(self.w, self.h);
     }
 
     //----------------------- Misc -------------------
     pub fn set_thickness(&mut self, thickness: usize) {
-        self.thickness = thickness;
+        self.state.thickness = thickness;
+    }
+
+    /// Sets the opacity of everything drawn afterwards, from 0.0 (invisible) to 1.0 (opaque).
+    /// It stacks with the alpha of `Rgba` colors.
+    pub fn set_global_alpha(&mut self, alpha: f32) {
+        self.state.global_alpha = alpha.clamp(0.0, 1.0);
+    }
+    pub fn get_global_alpha(&self) -> f32 {
+        self.state.global_alpha
+    }
+
+    /// Changes where (0, 0) is. Everything drawn afterwards uses it, including text, which always hangs down
+    /// from its position (so pos is the top-left corner of the text either way).
+    /// 
+    /// With TopLeft, rotate() turns things clockwise on the screen, like in js.
+    pub fn set_origin(&mut self, origin: Origin) {
+        self.state.origin = origin;
+    }
+    pub fn get_origin(&self) -> Origin {
+        self.state.origin
+    }
+
+    // Which row of the buffer y is on (the buffer is stored top row first)
+    fn row(&self, y: usize) -> usize {
+        match self.state.origin {
+            Origin::BottomLeft => self.h - 1 - y,
+            Origin::TopLeft => y,
+        }
+    }
+
+    /// Turns on anti-aliasing, lines, shapes and graphs drawn with GraphCtx get smooth edges that are blended
+    /// into whatever is behind them, instead of jagged ones that are either on a pixel or not.
+    pub fn set_anti_aliasing(&mut self, anti_aliasing: bool) {
+        self.state.anti_aliasing = anti_aliasing;
+    }
+    pub fn get_anti_aliasing(&self) -> bool {
+        self.state.anti_aliasing
+    }
+
+    /// How the ends of thick lines look, by default they are cut off flat at the end point (Butt)
+    pub fn set_line_cap(&mut self, cap: LineCap) {
+        self.state.line_cap = cap;
+    }
+    /// How the corners of thick lines look (e.g. in rect), by default they are sharp (Miter)
+    pub fn set_line_join(&mut self, join: LineJoin) {
+        self.state.line_join = join;
+    }
+    /// Miter corners longer than limit * thickness / 2 get cut off like a bevel, so very sharp
+    /// corners don't stick out forever. The default is 10 like in js.
+    pub fn set_miter_limit(&mut self, limit: f32) {
+        self.state.miter_limit = limit;
+    }
+
+    /// Makes lines dashed, like setLineDash in js. The segments are the lengths of the dashes and the gaps
+    /// in between, e.g. &[6.0, 3.0]. An odd number of segments gets repeated (so [5] is [5, 5]),
+    /// and an empty list makes lines solid again. Negative lengths are ignored, like in js.
+    /// 
+    /// Applies to draw_line, rect, the outlines of circles/arcs, paths and graphs.
+    pub fn set_line_dash(&mut self, segments: &[f32]) {
+        if segments.iter().any(|s| *s < 0.0 || !s.is_finite()) {
+            return;
+        }
+        self.state.line_dash = segments.to_vec();
+        if segments.len() % 2 == 1 {
+            self.state.line_dash.extend_from_slice(segments);
+        }
+    }
+    pub fn get_line_dash(&self) -> &[f32] {
+        &self.state.line_dash
+    }
+    /// How far into the dash pattern lines start, changing it over time makes the dashes "march"
+    pub fn set_line_dash_offset(&mut self, offset: f32) {
+        self.state.line_dash_offset = offset;
+    }
+    pub fn get_line_dash_offset(&self) -> f32 {
+        self.state.line_dash_offset
+    }
+
+    //----------------------- Transform -------------------
+    // Like in js, every coordinate given to the ctx goes through the current transformation before it's drawn.
+    // Line thickness gets scaled along with everything else.
+
+    /// Saves all of the drawing settings (thickness, alpha, transform, ...) so they can be brought back with restore()
+    pub fn save(&mut self) {
+        self.record(|_| Command::Save);
+        self.saved.push(self.state.clone());
+    }
+    /// Brings back the settings from the last save(). Does nothing if nothing was saved.
+    pub fn restore(&mut self) {
+        self.record(|_| Command::Restore);
+        if let Some(state) = self.saved.pop() {
+            self.state = state;
+        }
+    }
+    /// Moves everything drawn afterwards by (x, y)
+    pub fn translate(&mut self, x: f32, y: f32) {
+        self.state.transform = self.state.transform.multiply(&Transform::translation(x, y));
+    }
+    /// Rotates everything drawn afterwards counter-clockwise around the origin, angle is in radians
+    pub fn rotate(&mut self, angle: f32) {
+        self.state.transform = self.state.transform.multiply(&Transform::rotation(angle));
+    }
+    /// Stretches everything drawn afterwards by x horizontally and y vertically
+    pub fn scale(&mut self, x: f32, y: f32) {
+        self.state.transform = self.state.transform.multiply(&Transform::scaling(x, y));
+    }
+    /// Replaces the current transformation
+    pub fn set_transform(&mut self, transform: Transform) {
+        self.state.transform = transform;
+    }
+    pub fn reset_transform(&mut self) {
+        self.state.transform = Transform::identity();
+    }
+    pub fn get_transform(&self) -> Transform {
+        self.state.transform
+    }
+
+    // Puts points through the current transformation
+    fn to_device(&self, points: &[Vec2D<f32>]) -> Vec<Vec2D<f32>> {
+        points.iter().map(|p| self.state.transform.apply(p)).collect()
+    }
+
+    // The square around (x, y), half wide on each side, once it's been transformed
+    fn transformed_square(&self, x: f32, y: f32, half: f32) -> Vec<Vec2D<f32>> {
+        self.to_device(&[
+            Vec2D::new(x - half, y - half), Vec2D::new(x + half, y - half),
+            Vec2D::new(x + half, y + half), Vec2D::new(x - half, y + half),
+        ])
+    }
+
+    fn in_bounds(&self, p: &Vec2D<f32>) -> bool {
+        p.x >= -0.5 && p.y >= -0.5 && p.x < self.w as f32 - 0.5 && p.y < self.h as f32 - 0.5
+    }
+
+    //----------------------- Clipping -------------------
+    // Like in js, every clip is added on top of the previous one (only what's inside both is kept),
+    // and the only way to get rid of it is to save() before clipping and restore() after.
+
+    /// Only the rectangle with its bottom-left corner at (x, y) is drawn on from now on.
+    /// It clips the same pixels fill_rect would cover.
+    pub fn clip_rect(&mut self, x: f32, y: f32, width: f32, height: f32) {
+        let corners = [
+            Vec2D::new(x, y), Vec2D::new(x + width, y),
+            Vec2D::new(x + width, y + height), Vec2D::new(x, y + height),
+        ];
+        let transform = self.state.transform;
+        if transform.b != 0.0 || transform.c != 0.0 || self.svg.is_some() {
+            // Rotated, so it's not a rectangle on the screen anymore
+            return self.clip_shapes(&[self.to_device(&corners)], FillRule::NonZero, self.state.anti_aliasing);
+        }
+        self.record(|ctx| Command::Clip {
+            contours: vec![ctx.to_device(&corners)], rule: FillRule::NonZero, anti_aliasing: false, state: ctx.state.clone(),
+        });
+
+        let [a, b] = [transform.apply(&corners[0]), transform.apply(&corners[2])];
+        let [y0, y1] = [a.y.min(b.y).ceil() as i32, a.y.max(b.y).ceil() as i32 - 1];
+        // The clip is kept in rows of the buffer, so it stays in the same place if the origin changes
+        let [row0, row1] = match self.state.origin {
+            Origin::BottomLeft => [self.h as i32 - 1 - y1, self.h as i32 - 1 - y0],
+            Origin::TopLeft => [y0, y1],
+        };
+        let bounds = [a.x.min(b.x).ceil() as i32, row0, a.x.max(b.x).ceil() as i32 - 1, row1];
+        self.state.clip = Some(match &self.state.clip {
+            Some(clip) => clip.intersect_rect(bounds),
+            None => Clip::rect(bounds, self.w),
+        });
+    }
+
+    /// Only the inside of the current path is drawn on from now on (using the nonzero rule).
+    pub fn clip(&mut self) {
+        self.clip_with_rule(FillRule::NonZero);
+    }
+
+    pub fn clip_with_rule(&mut self, rule: FillRule) {
+        let contours: Vec<Vec<Vec2D<f32>>> = self.path.subpaths().iter().map(|sub| sub.points.clone()).collect();
+        self.clip_shapes(&contours, rule, self.state.anti_aliasing);
+    }
+
+    // Clips to shapes that have already been transformed
+    fn clip_shapes(&mut self, contours: &[Vec<Vec2D<f32>>], rule: FillRule, anti_aliasing: bool) {
+        self.record(|ctx| Command::Clip { contours: contours.to_vec(), rule, anti_aliasing, state: ctx.state.clone() });
+        if self.svg.is_some() {
+            return self.svg_clip(contours, rule);
+        }
+        let pixels = raster::fill_polygons(contours, rule, anti_aliasing, (self.w, self.h));
+        self.clip_to(&pixels);
+    }
+
+    fn clip_to(&mut self, pixels: &Coverage) {
+        let rows: Coverage = pixels.iter()
+            .filter(|p| p.1 >= 0 && (p.1 as usize) < self.h)
+            .map(|&(x, y, c)| (x, self.row(y as usize) as i32, c))
+            .collect();
+        self.state.clip = Some(Clip::from_coverage(&rows, self.state.clip.as_ref(), (self.w, self.h)));
     }
 
 
     
     //--------------------------------------------------- DRAWING ---------------------------
     pub fn clear_rect(&mut self, color: u32) {
+        self.record(|_| Command::Clear(color));
+        if self.svg.is_some() {
+            return self.svg_clear(Some(color));
+        }
         for i in self.buf.iter_mut() {
             *i = color;
         }
+        if let Some(alpha) = &mut self.alpha {
+            alpha.fill(255);
+        }
+        self.dirty.all(self.w, self.h);
     }
 
-    /// Draws a pixel, relative to the bottom-left corner of the screen. Color is hexadecimal.
-    pub fn draw_pixel(&mut self, x: usize, y: usize, color: u32) -> Result<(), String>{ // color is hexadecimal, dis is more memory efficient
-        
-        let len = self.buf.len();
+    /// Clears only a rectangle to the color, so things that didn't change don't have to be drawn again.
+    /// pos is the corner closest to the origin like in fill_rect, and it goes through the transform,
+    /// but it isn't clipped or blended (the pixels are replaced, like clear_rect).
+    pub fn clear_region(&mut self, pos: &Vec2D<usize>, width: usize, height: usize, color: u32) {
+        let corners = [
+            pos.f32(), Vec2D::new((pos.x + width) as f32, pos.y as f32),
+            Vec2D::new((pos.x + width) as f32, (pos.y + height) as f32), Vec2D::new(pos.x as f32, (pos.y + height) as f32),
+        ];
+        let state = self.state.clone();
+        self.state.clip = None;
+        self.state.svg_clip = None;
+        self.state.global_alpha = 1.0;
+        self.fill_shapes(&[self.to_device(&corners)], FillRule::NonZero, false, color, 1.0);
+        self.state = state;
+    }
+
+    /// The parts of the buffer that were drawn on since the last clear_dirty (or since the ctx was made).
+    /// Overlapping rects are merged, but they may cover a few pixels that didn't change.
+    pub fn dirty_rects(&self) -> Vec<DirtyRect> {
+        self.dirty.rects()
+    }
+    pub fn is_dirty(&self) -> bool {
+        !self.dirty.is_empty()
+    }
+    /// Marks everything as unchanged, e.g. after the buffer has been shown (SnorfWindow::update_dirty does this).
+    pub fn clear_dirty(&mut self) {
+        self.dirty.clear();
+    }
+
+    /// Draws a pixel, relative to the bottom-left corner of the screen (or the top-left one, see set_origin).
+    /// It goes through the transform like everything else. Color is hexadecimal.
+    pub fn draw_pixel(&mut self, x: usize, y: usize, color: u32) -> Result<(), Error>{ // color is hexadecimal, dis is more memory efficient
+        self.transformed_pixel(x as isize, y as isize, color, 1.0)
+    }
+
+    /// Same as draw_pixel, but the color is blended over what is already on the screen using its alpha.
+    pub fn draw_pixel_rgba(&mut self, x: usize, y: usize, color: &Rgba) -> Result<(), Error> {
+        self.transformed_pixel(x as isize, y as isize, color.to_hex(), color.alpha())
+    }
 
+    // A pixel that goes through the transform. If it's scaled or rotated the pixel is drawn as a transformed square.
+    fn transformed_pixel(&mut self, x: isize, y: isize, color: u32, alpha: f32) -> Result<(), Error> {
+        let transform = self.state.transform;
+        if transform.is_identity() && self.direct_pixels() {
+            if x < 0 || y < 0 {
+                return Err(self.out_of_bounds(x, y));
+            }
+            return self.blend_pixel(x as usize, y as usize, color, alpha);
+        }
+
+        let center = transform.apply(&Vec2D::new(x as f32, y as f32));
+        if !self.in_bounds(&center) {
+            return Err(self.out_of_bounds(center.x.round() as isize, center.y.round() as isize));
+        }
+        let square = self.transformed_square(x as f32, y as f32, 0.5);
+        self.fill_shapes(&[square], FillRule::NonZero, false, color, alpha);
+        Ok(())
+    }
 
-        if x > self.w || y > self.h  ||  x  >= (y+1) * self.w  || len < (y+1) * self.w {
-            Err(format!("Attempted to access ({x},{y}) when dimensions are ({},{})",self.w,self.h))
+    // Every pixel ends up here. alpha is multiplied with the global alpha and the clip, and the color is
+    // blended source-over onto the buffer.
+    fn blend_pixel(&mut self, x: usize, y: usize, color: u32, alpha: f32) -> Result<(), Error> {
+        let len = self.buf.len();
+
+        if x >= self.w || y >= self.h || len < self.h * self.w {
+            Err(self.out_of_bounds(x as isize, y as isize))
         }else {
-            let idx = (len- (y+1)*self.w) + x;
-            self.buf[idx] = color;  
+            let row = self.row(y);
+            let idx = row*self.w + x;
+            let mut alpha = alpha * self.state.global_alpha;
+            if let Some(clip) = &self.state.clip {
+                alpha *= clip.coverage(x, row);
+            }
+            if let Some(opacity) = &mut self.alpha {
+                // What's already there is only partly covering the pixel, so the new color counts for more
+                let dst = opacity[idx] as f32 / 255.0;
+                let out = alpha.min(1.0) + dst * (1.0 - alpha.min(1.0));
+                if out > 0.0 {
+                    self.buf[idx] = blend(self.buf[idx], color, alpha.min(1.0) / out);
+                    opacity[idx] = (out * 255.0).round() as u8;
+                }
+            }else if alpha >= 1.0 {
+                self.buf[idx] = color;
+            }else if alpha > 0.0 {
+                self.buf[idx] = blend(self.buf[idx], color, alpha);
+            }
+            if alpha > 0.0 {
+                self.dirty.add(x, row);
+            }
             Ok(())   
         }
     }
 
-    pub fn draw_scaled_pixel(&mut self, x: usize, y:usize, scale:usize, color:u32) -> Result<(), String>{
-        let offset = scale-1;
+    // Draws pixels with their coverage, drawing each pixel only once so overlapping shapes don't get
+    // blended twice (the most covered one wins). The points are in bottom-left coordinates and may be off screen.
+    fn fill_coverage(&mut self, pixels: Coverage, color: u32, alpha: f32) {
+        self.paint_coverage(pixels, |_, _| (color, alpha));
+    }
 
-        if x < offset || y < offset {
-            return Err(String::from("Attempted to draw pixel at negative coordinate" ))
+    // Same as fill_coverage, with the color and alpha of every pixel coming from paint (e.g. a gradient)
+    fn paint_coverage(&mut self, mut pixels: Coverage, paint: impl Fn(i32, i32) -> (u32, f32)) {
+        pixels.sort_unstable_by(|a, b| (a.0, a.1).cmp(&(b.0, b.1)).then(b.2.total_cmp(&a.2)));
+        pixels.dedup_by_key(|p| (p.0, p.1));
+        for (x, y, coverage) in pixels {
+            if x >= 0 && y >= 0 {
+                let (color, alpha) = paint(x, y);
+                let _ = self.blend_pixel(x as usize, y as usize, color, alpha * coverage);
+            }
         }
+    }
 
-        let mut err: String= String::new();
-        for _x in (x-offset)..=(x+offset) {
-            for _y in (y-offset)..=(y+offset) {
-                match self.draw_pixel(_x, _y, color) {
-                    Ok(()) => continue,
-                    Err(e) => {err = e},
-                }
-            }
+    // Strokes lines that have already been transformed (with whether they are closed), using the line settings.
+    // Everything that draws lines ends up here, so it can go to the SVG instead.
+    fn stroke_lines(&mut self, lines: &[(Vec<Vec2D<f32>>, bool)], color: u32, alpha: f32) {
+        self.record(|ctx| Command::Stroke { lines: lines.to_vec(), color, alpha, state: ctx.state.clone() });
+        if self.svg.is_some() {
+            return self.svg_shape(lines, Paint::Stroke, color, alpha);
         }
+        let pixels = lines.iter().flat_map(|(points, closed)| self.device_polyline_coverage(points, *closed)).collect();
+        self.fill_coverage(pixels, color, alpha);
+    }
 
-        if err.is_empty(){ Ok(()) }
-        else {Err(err)}
+    // Fills shapes that have already been transformed, like stroke_lines but for everything that's filled in
+    fn fill_shapes(&mut self, contours: &[Vec<Vec2D<f32>>], rule: FillRule, anti_aliasing: bool, color: u32, alpha: f32) {
+        self.record(|ctx| Command::Fill {
+            contours: contours.to_vec(), rule, anti_aliasing, color, alpha, state: ctx.state.clone(),
+        });
+        if self.svg.is_some() {
+            let lines: Vec<_> = contours.iter().map(|contour| (contour.clone(), true)).collect();
+            return self.svg_shape(&lines, Paint::Fill(rule), color, alpha);
+        }
+        let pixels = raster::fill_polygons(contours, rule, anti_aliasing, (self.w, self.h));
+        self.fill_coverage(pixels, color, alpha);
     }
 
+    // Whether pixels can be written straight to the buffer, instead of going through stroke_lines or fill_shapes
+    // (which the SVG and recordings need)
+    fn direct_pixels(&self) -> bool {
+        self.svg.is_none() && self.recording.is_none()
+    }
 
-    /// Draws a line, using brezenheimer algorithm for speeeed because it avoids float calculations
+    /// Draws a square that goes scale-1 pixels out from (x, y) on every side.
     /// 
-    /// It's kinda bad at doing thick lines, I don't really know how to do that properly so I used a hacky method.
-    pub fn draw_line(&mut self, start: &Vec2D<usize>, end: &Vec2D<usize>, color: u32) -> Result<(), String> {
-        const PIXEL_SCALE:usize = 1;
+    /// The part that's on the screen is always drawn, but it's an error if some of it is off the screen.
+    pub fn draw_scaled_pixel(&mut self, x: usize, y:usize, scale:usize, color:u32) -> Result<(), Error>{
+        self.scaled_pixel(isize::try_from(x).unwrap_or(isize::MAX), isize::try_from(y).unwrap_or(isize::MAX), scale, color)
+    }
 
-        let thickness = self.thickness.clone();
-        let [x0, y0, x1, y1] = [start.x as i32, start.y as i32, end.x as i32, end.y as i32];
-        
-        let mut draw_single_line = |x0: i32, y0: i32, x1: i32, y1: i32| -> Result<(), String> { // x0,y0 is the start position
-            let mut x:i32 = x0; let mut y:i32 = y0 as i32;
-            let dx = (x1 - x0).abs();   let sx = if x0<x1{1} else{-1}; // how it increments
-            let dy = - (y1 - y0).abs(); let sy = if y0<y1{1} else{-1};
-            let mut error = dx+dy;
-    
-            loop {
-                if x > 0 && y > 0 {
-                    let _ = self.draw_scaled_pixel(x as usize,y as usize, PIXEL_SCALE, color);
-                }
-                if x==x1 && y==y1 {break}
-                let e2 = 2*error;
-    
-                if e2 >= dy {
-                    if x==x1{break}
-                    error = error + dy;
-                    x+=sx;
-                }
-                if e2 <= dx {
-                    if y==y1{break}
-                    error = error + dx;
-                    y+=sy;
-                }
+    fn scaled_pixel(&mut self, x: isize, y: isize, scale: usize, color: u32) -> Result<(), Error> {
+        if scale == 0 {
+            return Ok(());
+        }
+        let offset = isize::try_from(scale - 1).unwrap_or(isize::MAX);
+
+        if !self.state.transform.is_identity() || !self.direct_pixels() {
+            let square = self.transformed_square(x as f32, y as f32, offset as f32 + 0.5);
+            let inside = square.iter().all(|p| self.in_bounds(p));
+            self.fill_shapes(&[square], FillRule::NonZero, false, color, 1.0);
+            return if inside { Ok(()) } else { Err(self.out_of_bounds(x, y)) };
+        }
+
+        // Only go over the part that's on the screen. Saturating, the square can reach past the ends of isize
+        let [left, bottom] = [x.saturating_sub(offset), y.saturating_sub(offset)];
+        let [right, top] = [x.saturating_add(offset), y.saturating_add(offset)];
+        let [x0, y0] = [left.max(0), bottom.max(0)];
+        let [x1, y1] = [right.min(self.w as isize - 1), top.min(self.h as isize - 1)];
+        for _x in x0..=x1 {
+            for _y in y0..=y1 {
+                let _ = self.blend_pixel(_x as usize, _y as usize, color, 1.0);
             }
-            Ok(())
-        };
+        }
 
-        // Thicken up the line
-        if thickness > 1 {
-            // Difference vector, with magnitude 0.5
-            let norm: Vec2D<f32> = Vec2D::new((x1-x0) as f32, (y1-y0) as f32).rotate(PI/2.0);
+        if x0 == left && y0 == bottom && x1 == right && y1 == top { Ok(()) }
+        else { Err(self.out_of_bounds(x, y)) }
+    }
 
-            let mut norm_size = 0.5;
-            
-            let start = Vec2D::new(x0 as f32, y0 as f32);
-            let end = Vec2D::new(x1 as f32, y1 as f32);
+    fn out_of_bounds(&self, x: isize, y: isize) -> Error {
+        Error::OutOfBounds { x, y, width: self.w, height: self.h }
+    }
+
+
+    /// Draws a line through the transform, with the line settings (thickness, dashes, anti-aliasing).
+    /// 
+    /// 1 pixel thick lines are stepped along pixel by pixel (Bresenham, or Wu with anti-aliasing). Thicker lines are
+    /// turned into a polygon and filled, with the ends drawn according to `set_line_cap`.
+    pub fn draw_line(&mut self, start: &Vec2D<usize>, end: &Vec2D<usize>, color: u32) -> Result<(), Error> {
+        self.polyline(&[start.f32(), end.f32()], false, color, 1.0);
+        Ok(())
+    }
 
-            let _ = draw_single_line(x0, y0, x1, y1);
-            for _ in 1..thickness {
-                let norm_scaled = norm.with_magnitude(norm_size);
-                let start_1 = start.add_vec(&norm_scaled);
-                let start_2 = start.sub_vec(&norm_scaled);
+    /// Same as draw_line, but blended using the alpha of the color.
+    pub fn draw_line_rgba(&mut self, start: &Vec2D<usize>, end: &Vec2D<usize>, color: &Rgba) -> Result<(), Error> {
+        self.polyline(&[start.f32(), end.f32()], false, color.to_hex(), color.alpha());
+        Ok(())
+    }
 
-                let end_1 = end.add_vec(&norm_scaled);
-                let end_2 = end.sub_vec(&norm_scaled);
+    // Draws the lines joining the points, which go through the transform first
+    fn polyline(&mut self, points: &[Vec2D<f32>], closed: bool, color: u32, alpha: f32) {
+        self.stroke_lines(&[(self.to_device(points), closed)], color, alpha);
+    }
 
-                let _ = draw_single_line(start_1.x as i32, start_1.y as i32, end_1.x as i32, end_1.y as i32);
-                let _ = draw_single_line(start_2.x as i32, start_2.y as i32, end_2.x as i32, end_2.y as i32);
+    // Gets every pixel covered by the lines joining the points (already transformed). Thin lines are drawn
+    // one by one, thick ones are stroked as polygons with caps and joins.
+    fn device_polyline_coverage(&self, points: &[Vec2D<f32>], closed: bool) -> Coverage {
+        // A pattern of only zeros would never get anywhere, so it's drawn solid like in js
+        if self.state.line_dash.iter().sum::<f32>() > 0.0 {
+            // The dashes are measured before transforming, so they get scaled like everything else
+            let scale = self.state.transform.scale_factor();
+            let pattern: Vec<f32> = self.state.line_dash.iter().map(|s| s * scale).collect();
+            // Only the dashes near the screen are made, with room for the caps and joins of ones that stick onto it
+            let pad = self.state.thickness as f32 * scale * self.state.miter_limit.max(1.0) + 2.0;
+            let bounds = [-pad, -pad, self.w as f32 + pad, self.h as f32 + pad];
+            let dashes = stroke::dash_polyline(points, closed, &pattern, self.state.line_dash_offset * scale, bounds);
+            return dashes.iter().flat_map(|dash| self.solid_polyline_coverage(dash, false)).collect();
+        }
+        self.solid_polyline_coverage(points, closed)
+    }
 
+    fn solid_polyline_coverage(&self, points: &[Vec2D<f32>], closed: bool) -> Coverage {
+        let width = self.state.thickness as f32 * self.state.transform.scale_factor();
+        if width > 1.0 {
+            let style = StrokeStyle {
+                width,
+                cap: self.state.line_cap,
+                join: self.state.line_join,
+                miter_limit: self.state.miter_limit,
+            };
+            let polygons = stroke::stroke_polyline(points, closed, &style);
+            return raster::fill_polygons(&polygons, FillRule::NonZero, self.state.anti_aliasing, (self.w, self.h));
+        }
 
-                norm_size += 0.5;
+        let mut pixels: Coverage = Vec::new();
+        if let [point] = points {
+            // No direction, so it's just a dot
+            Self::bresenham(point, point, &mut pixels);
+        }
+        let segment_count = if closed && points.len() > 2 { points.len() } else { points.len().saturating_sub(1) };
+        for i in 0..segment_count {
+            let Some((start, end)) = raster::clip_segment(&points[i], &points[(i + 1) % points.len()], (self.w, self.h)) else {
+                continue;
+            };
+            let (start, end) = (&start, &end);
+            if self.state.anti_aliasing {
+                raster::wu_line(start.x, start.y, end.x, end.y, &mut pixels);
+            }else {
+                Self::bresenham(start, end, &mut pixels);
             }
-        }else {
-            let _ = draw_single_line(x0, y0, x1, y1);
         }
+        pixels
+    }
 
-        Ok(())
-
-        
+    fn bresenham(start: &Vec2D<f32>, end: &Vec2D<f32>, pixels: &mut Coverage) {
+        let [x0, y0, x1, y1] = [start.x, start.y, end.x, end.y].map(|n| n.round() as i32);
+        let mut x:i32 = x0; let mut y:i32 = y0;
+        let dx = (x1 - x0).abs();   let sx = if x0<x1{1} else{-1}; // how it increments
+        let dy = - (y1 - y0).abs(); let sy = if y0<y1{1} else{-1};
+        let mut error = dx+dy;
+
+        loop {
+            pixels.push((x, y, 1.0));
+            if x==x1 && y==y1 {break}
+            let e2 = 2*error;
+
+            if e2 >= dy {
+                if x==x1{break}
+                error += dy;
+                x+=sx;
+            }
+            if e2 <= dx {
+                if y==y1{break}
+                error += dx;
+                y+=sy;
+            }
+        }
     }
 
     pub fn rect(&mut self, pos: &Vec2D<usize>, width: usize, height: usize, color: u32) {
-        let sw:&Vec2D<usize> = pos; 
-        let se:&Vec2D<usize> = &[pos.x+width, pos.y].into(); // south-east
-        let ne:&Vec2D<usize> = &[pos.x+width, pos.y+height].into(); 
-        let nw:&Vec2D<usize> = &[pos.x, pos.y+height].into();
+        self.rect_outline(&pos.f32(), width as f32, height as f32, color, 1.0);
+    }
 
-        self.draw_line(sw, se, color);
-        self.draw_line(se, ne, color);
-        self.draw_line(ne, nw, color);
-        self.draw_line(nw, sw, color);
+    /// Same as rect, but blended using the alpha of the color.
+    pub fn rect_rgba(&mut self, pos: &Vec2D<usize>, width: usize, height: usize, color: &Rgba) {
+        self.rect_outline(&pos.f32(), width as f32, height as f32, color.to_hex(), color.alpha());
     }
 
+    fn rect_outline(&mut self, pos: &Vec2D<f32>, width: f32, height: f32, color: u32, alpha: f32) {
+        let sw:Vec2D<f32> = pos.clone(); 
+        let se:Vec2D<f32> = [sw.x + width, sw.y].into(); // south-east
+        let ne:Vec2D<f32> = [sw.x + width, sw.y + height].into(); 
+        let nw:Vec2D<f32> = [sw.x, sw.y + height].into();
+
+        // Closed so the corners get joined
+        self.polyline(&[sw, se, ne, nw], true, color, alpha);
+    }
+
+    /// Fills a rectangle, pos is the bottom-left corner. It covers exactly width x height pixels.
+    pub fn fill_rect(&mut self, pos: &Vec2D<usize>, width: usize, height: usize, color: u32) {
+        self.fill_rect_rgba(pos, width, height, &Rgba::from_hex(color, 255));
+    }
+
+    /// Same as fill_rect, but blended using the alpha of the color.
+    pub fn fill_rect_rgba(&mut self, pos: &Vec2D<usize>, width: usize, height: usize, color: &Rgba) {
+        self.fill_rect_f_rgba(&pos.f32(), width as f32, height as f32, color);
+    }
+
+    fn fill_rect_f_rgba(&mut self, sw: &Vec2D<f32>, width: f32, height: f32, color: &Rgba) {
+        let corners = Self::rect_corners(sw, width, height);
+        let anti_aliasing = self.rect_anti_aliasing();
+        self.fill_shapes(&[self.to_device(&corners)], FillRule::NonZero, anti_aliasing, color.to_hex(), color.alpha());
+    }
+
+    fn rect_corners(sw: &Vec2D<f32>, width: f32, height: f32) -> Vec<Vec2D<f32>> {
+        vec![
+            sw.clone(), 
+            Vec2D::new(sw.x + width, sw.y), 
+            Vec2D::new(sw.x + width, sw.y + height), 
+            Vec2D::new(sw.x, sw.y + height),
+        ]
+    }
+
+    // Only smooth the edges of filled rects if they've been rotated, otherwise the edges are already on the pixels
+    fn rect_anti_aliasing(&self) -> bool {
+        let transform = self.state.transform;
+        self.state.anti_aliasing && (transform.b != 0.0 || transform.c != 0.0)
+    }
+
+    /// Fills the inside of the polygon going through the points, it's closed automatically.
+    /// 
+    /// The fill rule decides what happens where the outline crosses itself, e.g. for a star drawn in one go
+    /// NonZero fills the middle and EvenOdd leaves a hole.
+    pub fn fill_polygon(&mut self, points: &[Vec2D<f32>], rule: FillRule, color: u32) {
+        self.fill_polygon_rgba(points, rule, &Rgba::from_hex(color, 255));
+    }
+
+    /// Same as fill_polygon, but blended using the alpha of the color.
+    pub fn fill_polygon_rgba(&mut self, points: &[Vec2D<f32>], rule: FillRule, color: &Rgba) {
+        self.fill_shapes(&[self.to_device(points)], rule, self.state.anti_aliasing, color.to_hex(), color.alpha());
+    }
+
+    //------------------- Round things
+    // The angles are in radians and go counter-clockwise, starting from the right of the center.
+
+    /// Draws the outline of a circle, using the ctx thickness.
+    pub fn draw_circle(&mut self, center: &Vec2D<usize>, radius: usize, color: u32) {
+        self.draw_ellipse(center, radius, radius, color);
+    }
+
+    pub fn fill_circle(&mut self, center: &Vec2D<usize>, radius: usize, color: u32) {
+        self.fill_ellipse(center, radius, radius, color);
+    }
+
+    /// Same as fill_circle, but blended using the alpha of the color (e.g. for overlapping markers).
+    pub fn fill_circle_rgba(&mut self, center: &Vec2D<usize>, radius: usize, color: &Rgba) {
+        let points = stroke::ellipse(&center.f32(), radius as f32, radius as f32, self.state.transform.scale_factor());
+        self.fill_polygon_rgba(&points, FillRule::NonZero, color);
+    }
+
+    /// Draws the outline of an ellipse, rx and ry are the radius along the x and y axis.
+    pub fn draw_ellipse(&mut self, center: &Vec2D<usize>, rx: usize, ry: usize, color: u32) {
+        let points = stroke::ellipse(&center.f32(), rx as f32, ry as f32, self.state.transform.scale_factor());
+        self.polyline(&points, true, color, 1.0);
+    }
+
+    pub fn fill_ellipse(&mut self, center: &Vec2D<usize>, rx: usize, ry: usize, color: u32) {
+        let points = stroke::ellipse(&center.f32(), rx as f32, ry as f32, self.state.transform.scale_factor());
+        self.fill_polygon(&points, FillRule::NonZero, color);
+    }
+
+    /// Draws part of a circle, from start_angle to end_angle. If end_angle is smaller it goes clockwise instead.
+    /// 
+    /// The ends use the line cap like any other line.
+    pub fn arc(&mut self, center: &Vec2D<usize>, radius: usize, start_angle: f32, end_angle: f32, color: u32) {
+        let scale = self.state.transform.scale_factor();
+        let points = stroke::arc(&center.f32(), radius as f32, radius as f32, start_angle, end_angle, scale);
+        self.polyline(&points, false, color, 1.0);
+    }
+
+    //------------------- Off-screen friendly versions
+    // These take signed or float coordinates, so things can be partly (or completely) off the screen.
+    // Whatever is on the screen gets drawn and the rest is clipped off, there are no errors.
+
+    pub fn draw_pixel_i(&mut self, x: isize, y: isize, color: u32) {
+        let _ = self.transformed_pixel(x, y, color, 1.0);
+    }
+
+    pub fn draw_scaled_pixel_i(&mut self, x: isize, y: isize, scale: usize, color: u32) {
+        let _ = self.scaled_pixel(x, y, scale, color);
+    }
+
+    pub fn draw_line_i(&mut self, start: &Vec2D<isize>, end: &Vec2D<isize>, color: u32) {
+        self.draw_line_f(&start.f32(), &end.f32(), color);
+    }
+
+    /// The ends don't have to be on a pixel, with anti-aliasing on the line is placed exactly.
+    pub fn draw_line_f(&mut self, start: &Vec2D<f32>, end: &Vec2D<f32>, color: u32) {
+        self.polyline(&[start.clone(), end.clone()], false, color, 1.0);
+    }
+
+    pub fn rect_f(&mut self, pos: &Vec2D<f32>, width: f32, height: f32, color: u32) {
+        self.rect_outline(pos, width, height, color, 1.0);
+    }
+
+    pub fn fill_rect_f(&mut self, pos: &Vec2D<f32>, width: f32, height: f32, color: u32) {
+        self.fill_rect_f_rgba(pos, width, height, &Rgba::from_hex(color, 255));
+    }
+
+    pub fn draw_circle_f(&mut self, center: &Vec2D<f32>, radius: f32, color: u32) {
+        let points = stroke::ellipse(center, radius, radius, self.state.transform.scale_factor());
+        self.polyline(&points, true, color, 1.0);
+    }
+
+    pub fn fill_circle_f(&mut self, center: &Vec2D<f32>, radius: f32, color: u32) {
+        let points = stroke::ellipse(center, radius, radius, self.state.transform.scale_factor());
+        self.fill_polygon(&points, FillRule::NonZero, color);
+    }
+
+    //------------------- Paths
+    // Works like paths in js: begin_path, build the shape with move_to/line_to/curves, then stroke or fill it.
+    // Coordinates are relative to the origin (bottom-left unless set_origin changed it), like everything else.
+    // The points are transformed as they're added, so changing the transform halfway through a path only affects the rest of it.
+
+    /// Throws away the current path and starts a new, empty one.
+    pub fn begin_path(&mut self) {
+        self.path = Path::new();
+    }
+    /// Starts a new sub-path at (x, y), without drawing a line to it.
+    pub fn move_to(&mut self, x: f32, y: f32) {
+        let p = self.state.transform.apply(&Vec2D::new(x, y));
+        self.path.move_to(p.x, p.y);
+    }
+    pub fn line_to(&mut self, x: f32, y: f32) {
+        let p = self.state.transform.apply(&Vec2D::new(x, y));
+        self.path.line_to(p.x, p.y);
+    }
+    /// A curve from the current point to (x, y), pulled towards the control point (cpx, cpy).
+    pub fn quadratic_curve_to(&mut self, cpx: f32, cpy: f32, x: f32, y: f32) {
+        // Transforming the control points transforms the whole curve
+        let [cp, p] = [[cpx, cpy], [x, y]].map(|[x, y]| self.state.transform.apply(&Vec2D::new(x, y)));
+        self.path.quadratic_curve_to(cp.x, cp.y, p.x, p.y);
+    }
+    /// A cubic bézier curve from the current point to (x, y).
+    pub fn bezier_curve_to(&mut self, cp1x: f32, cp1y: f32, cp2x: f32, cp2y: f32, x: f32, y: f32) {
+        let [cp1, cp2, p] = [[cp1x, cp1y], [cp2x, cp2y], [x, y]].map(|[x, y]| self.state.transform.apply(&Vec2D::new(x, y)));
+        self.path.bezier_curve_to(cp1.x, cp1.y, cp2.x, cp2.y, p.x, p.y);
+    }
+    /// A rounded corner at (x1, y1), on the way to (x2, y2).
+    pub fn arc_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, radius: f32) {
+        // Circles don't stay circles when they are stretched, so the arc is worked out before transforming
+        let transform = self.state.transform;
+        let (Some(current), Some(inverse)) = (self.path.current_point(), transform.invert()) else {
+            return self.line_to(x1, y1);
+        };
+        let mut corner = Path::new();
+        let start = inverse.apply(current);
+        corner.move_to(start.x, start.y);
+        corner.arc_to(x1, y1, x2, y2, radius);
+
+        for p in corner.subpaths()[0].points.iter().skip(1) {
+            let p = transform.apply(p);
+            self.path.line_to(p.x, p.y);
+        }
+    }
+    /// Draws a line back to the start of the current sub-path.
+    pub fn close_path(&mut self) {
+        self.path.close_path();
+    }
+
+    /// Draws the outline of the current path, with the ctx thickness, line caps and joins.
+    pub fn stroke(&mut self, color: u32) {
+        let lines: Vec<(Vec<Vec2D<f32>>, bool)> = self.path.subpaths().iter()
+            .filter(|sub| sub.points.len() > 1)
+            .map(|sub| (sub.points.clone(), sub.closed))
+            .collect();
+        self.stroke_lines(&lines, color, 1.0);
+    }
+
+    /// Fills the current path using the nonzero rule, every sub-path is closed automatically.
+    pub fn fill(&mut self, color: u32) {
+        self.fill_with_rule(FillRule::NonZero, color);
+    }
+
+    pub fn fill_with_rule(&mut self, rule: FillRule, color: u32) {
+        let contours: Vec<Vec<Vec2D<f32>>> = self.path.subpaths().iter().map(|sub| sub.points.clone()).collect();
+        self.fill_shapes(&contours, rule, self.state.anti_aliasing, color, 1.0);
+    }
+
+
+    pub fn set_text_align(&mut self, align: TextAlign) {
+        self.state.text_align = align;
+    }
+    pub fn get_text_align(&self) -> TextAlign {
+        self.state.text_align
+    }
+    pub fn set_text_baseline(&mut self, baseline: TextBaseline) {
+        self.state.text_baseline = baseline;
+    }
+    pub fn get_text_baseline(&self) -> TextBaseline {
+        self.state.text_baseline
+    }
+
+    /// The color draw_text uses, black by default. draw_text_rgba still takes its own color.
+    pub fn set_text_color(&mut self, color: &Rgba) {
+        self.state.text_color = (color.to_hex(), color.alpha());
+    }
+    /// Draws a box in this color (it can be see-through) behind all text, e.g. to highlight it or keep it readable
+    /// on top of a graph. None for no box, which is the default.
+    /// ```
+    /// # use bml_grapher::{Ctx, Rgba};
+    /// let mut ctx = Ctx::headless(320, 240);
+    /// ctx.clear_rect(0x202020);
+    /// ctx.set_text_color(&Rgba::from_hex(0xffffff, 255));
+    /// ctx.set_text_background(Some(&Rgba::from_hex(0x4328ed, 128)));
+    /// ctx.draw_text(&[10, 200].into(), "max: 5.2", 1);
+    /// ```
+    pub fn set_text_background(&mut self, color: Option<&Rgba>) {
+        self.state.text_background = color.map(|color| (color.to_hex(), color.alpha()));
+    }
+
+    /// How many empty rows of pixels are between lines of text (text is split into lines at \n), at scale 1.
+    /// The default is 1.
+    pub fn set_line_spacing(&mut self, spacing: usize) {
+        self.state.line_spacing = spacing;
+    }
+    /// What's drawn instead of characters the font doesn't have, '?' by default. The built-in font has
+    /// ASCII and Latin-1 (up to ÿ).
+    pub fn set_replacement_char(&mut self, replacement: char) {
+        self.state.replacement = replacement;
+    }
+
+    // The lines of text as they're drawn (see text::lines), with how far apart their tops are
+    pub(crate) fn text_lines(&self, text: &str, scale: usize) -> (Vec<String>, f32) {
+        let spacing = self.state.line_spacing.saturating_mul(scale) as f32;
+        match &self.state.font {
+            Some(font) => {
+                let line_height = font.measure("", self.state.font_size * scale as f32).1;
+                (text::lines(text, self.state.replacement, |c| font.has_glyph(c)), line_height + spacing)
+            },
+            None => (text::lines(text, self.state.replacement, text::has_glyph), 8.0 * scale as f32 + spacing),
+        }
+    }
+
+    /// How wide and tall text is when it's drawn with draw_text (with the font and font size that are set).
+    /// ```
+    /// # use bml_grapher::{Ctx, TextAlign};
+    /// let mut ctx = Ctx::headless(320, 240);
+    /// assert_eq!(ctx.measure_text("-1.5", 2), (64.0, 16.0));
+    /// // Right-aligned, the text ends at x = 100
+    /// ctx.set_text_align(TextAlign::Right);
+    /// ctx.draw_text(&[100, 50].into(), "-1.5", 2);
+    /// ```
+    /// With more than one line, it's the widest line and all of them together.
+    pub fn measure_text(&self, text: &str, scale: usize) -> (f32, f32) {
+        match &self.state.font {
+            Some(font) => {
+                let size = self.state.font_size * scale as f32;
+                let (lines, advance) = self.text_lines(text, scale);
+                let width = lines.iter().map(|line| font.measure(line, size).0).fold(0.0, f32::max);
+                let spacing = advance - font.measure("", size).1;
+                (width, lines.len() as f32 * advance - spacing)
+            },
+            None => {
+                let (width, height) = text::measure(text, scale, self.state.line_spacing, self.state.replacement);
+                (width as f32, height as f32)
+            },
+        }
+    }
+
+    // Where a point of the text ends up (before the transform), from x going right and y going down from the
+    // top-left corner of the text. That corner is moved away from pos by the text align and baseline.
+    pub(crate) fn text_placement(&self, pos: &Vec2D<usize>, text: &str, scale: usize) -> impl Fn(f32, f32) -> Vec2D<f32> {
+        let (width, height) = self.measure_text(text, scale);
+        let mut dx = match self.state.text_align {
+            TextAlign::Left => 0.0,
+            TextAlign::Center => width / 2.0,
+            TextAlign::Right => width,
+        };
+        let mut dy = match self.state.text_baseline {
+            TextBaseline::Top => 0.0,
+            TextBaseline::Middle => height / 2.0,
+            TextBaseline::Bottom => height,
+        };
+        if self.state.font.is_none() {
+            // The bitmap font stays on whole pixels
+            (dx, dy) = (dx.round(), dy.round());
+        }
+        // The middle of the first pixel is at pos (bottom-left origin: one pixel below it)
+        let (x, y, origin) = (pos.x as f32 - 0.5 - dx, pos.y as f32 - 0.5, self.state.origin);
+        move |lx, ly| match origin {
+            Origin::BottomLeft => Vec2D::new(x + lx, y - ly + dy),
+            Origin::TopLeft => Vec2D::new(x + lx, y + ly - dy),
+        }
+    }
 
     pub fn draw_text(&mut self, pos: &Vec2D<usize>, text: &str, scale:usize) {
-        let text_obj = Text::new(self.w, self.h, scale, true);
-        text_obj.draw(&mut self.buf, (pos.x, self.h-pos.y), text);
+        let (color, alpha) = self.state.text_color;
+        self.draw_text_rgba(pos, text, scale, &Rgba::from_hex(color, (alpha * 255.0).round() as u32));
+    }
+
+    // The box behind the text, it's part of the text so it isn't recorded on its own
+    fn draw_text_background(&mut self, pos: &Vec2D<usize>, text: &str, scale: usize) {
+        let Some((color, alpha)) = self.state.text_background else { return };
+        let (width, height) = self.measure_text(text, scale);
+        if width == 0.0 {
+            return;
+        }
+        // A pixel of space around the letters (at scale 1)
+        let place = self.text_placement(pos, text, scale);
+        let pad = scale as f32;
+        let corners = [(-pad, -pad), (width + pad, -pad), (width + pad, height + pad), (-pad, height + pad)];
+        let contour = self.to_device(&corners.map(|(x, y)| place(x, y)));
+        if self.svg.is_some() {
+            return self.svg_shape(&[(contour, true)], Paint::Fill(FillRule::NonZero), color, alpha);
+        }
+        let pixels = raster::fill_polygons(&[contour], FillRule::NonZero, self.rect_anti_aliasing(), (self.w, self.h));
+        self.fill_coverage(pixels, color, alpha);
+    }
+
+    /// Draws text in the color given, blended using its alpha.
+    pub fn draw_text_rgba(&mut self, pos: &Vec2D<usize>, text: &str, scale:usize, color: &Rgba) {
+        self.record(|ctx| Command::Text {
+            pos: pos.clone(), text: text.to_string(), scale, color: color.to_hex(), alpha: color.alpha(), state: ctx.state.clone(),
+        });
+        self.draw_text_background(pos, text, scale);
+        if let Some(font) = self.state.font.clone() {
+            return self.draw_font_text(&font, pos, text, scale, color.to_hex(), color.alpha());
+        }
+        if self.svg.is_some() {
+            return self.svg_text(pos, text, scale, color.to_hex(), color.alpha());
+        }
+        let mut text_obj = Text::new(self.w, self.h, scale, true);
+        text_obj.set_line_spacing(self.state.line_spacing);
+        text_obj.set_replacement(self.state.replacement);
+        let (hex, alpha) = (color.to_hex(), color.alpha());
+        let transform = self.state.transform;
+
+        // The text is laid out top-down, the middles of its pixels are placed like everything else
+        let place = self.text_placement(pos, text, scale);
+        let center = |x: usize, y: usize| place(x as f32 + 0.5, y as f32 + 0.5);
+        let mut pixels: Coverage = Vec::new();
+        if transform.is_translation() {
+            let (dx, dy) = (transform.e.round() as i32, transform.f.round() as i32);
+            text_obj.for_each_pixel((0, 0), text, |x, y, _| {
+                let p = center(x, y);
+                // Far off the screen stays off it, instead of overflowing
+                pixels.push(((p.x.round() as i32).saturating_add(dx), (p.y.round() as i32).saturating_add(dy), 1.0));
+            });
+        }else {
+            // Rotated or scaled, every pixel of the font becomes a little transformed square
+            let mut squares: Vec<Vec<Vec2D<f32>>> = Vec::new();
+            text_obj.for_each_pixel((0, 0), text, |x, y, _| {
+                let p = center(x, y);
+                squares.push(self.transformed_square(p.x, p.y, 0.5));
+            });
+            pixels = raster::fill_polygons(&squares, FillRule::NonZero, self.state.anti_aliasing, (self.w, self.h));
+        }
+        self.fill_coverage(pixels, hex, alpha);
     }
 }
 
@@ -248,16 +1134,22 @@ impl Ctx {
 //*----------------------------------------- */
 //
 
+#[derive(Clone)]
 pub struct GraphSettings {
     axis_offset: usize,
     min_xnum: f32,
     max_xnum: f32,
     min_ynum: f32,
     max_ynum: f32,
+    grid_dash: Vec<f32>, // dash pattern of the grid lines, empty for solid
 }
 impl GraphSettings {
     pub fn new(axis_offset: usize, min_xnum:f32, max_xnum:f32, min_ynum:f32, max_ynum:f32) -> Self {
-        GraphSettings{axis_offset, min_xnum, max_xnum, min_ynum, max_ynum}
+        GraphSettings{axis_offset, min_xnum, max_xnum, min_ynum, max_ynum, grid_dash: Vec::new()}
+    }
+    /// Makes the grid lines of draw_axis dashed, it works like `Ctx::set_line_dash`. e.g. &[1.0, 3.0] for a dotted grid.
+    pub fn set_grid_dash(&mut self, segments: &[f32]) {
+        self.grid_dash = segments.to_vec();
     }
     pub fn get_border_offsets(&self) -> [f32; 4] {
         [self.min_xnum, self.max_xnum, self.min_ynum, self.max_ynum]
@@ -280,67 +1172,123 @@ impl<'a> GraphCtx<'a> {
     /// step = how much the numbers increment
     /// min_num = the starting number
     /// max_num = the final number
-    pub fn draw_axis(&mut self, y_axis: bool, step: f32, min_num: f32, max_num: f32 ) {
+    pub fn draw_axis(&mut self, y_axis: bool, step: f32, min_num: f32, max_num: f32 ) -> Result<(), Error> {
         let [w,h] = [self.ctx.w, self.ctx.h];
         let offset = self.settings.axis_offset;
-        let num_offset = offset / 2;
+        // The grid lines stick out of the axis a bit as ticks, the numbers are just past them
+        let tick = 3.min(offset);
+        let label = offset.saturating_sub(tick + 2);
+        self.check_offset(offset)?;
+        Self::check_range(min_num, max_num)?;
+        let length = if y_axis { h } else { w }.saturating_sub(2 * offset);
+        let pix_step = (step * length as f32 / (max_num - min_num)) as usize;
+        if pix_step == 0 {
+            // The numbers would be on top of each other (or it would never get to max_num)
+            return Err(Error::InvalidStep { step });
+        }
+
+        // The grid can be dashed, the axes are always solid
+        self.ctx.save();
+        self.ctx.set_origin(Origin::BottomLeft); // graphs go up
+        self.ctx.set_line_dash(&self.settings.grid_dash);
         
 
         if y_axis {
             let end_point = Vec2D::new(offset,h-offset);
-            let pix_step =  (step * (h- 2* offset) as f32 / (max_num - min_num))  as usize; 
 
             let mut curr_num = min_num;
-            for y in (offset..=(h - offset)).step_by(pix_step) {
-                let text_pos = Vec2D::new(offset - num_offset as usize, y);
+            // Numbers end left of the axis, in line with their grid line
+            self.ctx.set_text_align(TextAlign::Right);
+            self.ctx.set_text_baseline(TextBaseline::Middle);
+            for y in (offset..=h.saturating_sub(offset)).step_by(pix_step) {
                 // Draw grid line
                 if y > offset {
-                    self.ctx.draw_line(&text_pos, &Vec2D::new(w-offset, text_pos.y), Hex::from_word("grey"));
+                    let _ = self.ctx.draw_line(&Vec2D::new(offset - tick, y), &Vec2D::new(w-offset, y), Hex::from_word("grey"));
                 }
                 
-                self.ctx.draw_text(&text_pos, &curr_num.to_string(), 1);
+                self.ctx.draw_text(&Vec2D::new(label, y), &curr_num.to_string(), 1);
 
                 curr_num += step;
             }
 
             // Y axis
-            self.ctx.draw_line(&Vec2D::new(offset,offset), &end_point, 0x000000);
-            //  Y text
-            self.ctx.draw_text(&[offset/2,h-offset/2].into(), "Y", 1);
+            self.ctx.set_line_dash(&[]);
+            let _ = self.ctx.draw_line(&Vec2D::new(offset,offset), &end_point, 0x000000);
+            //  Y text, above the axis
+            self.ctx.set_text_align(TextAlign::Center);
+            self.ctx.set_text_baseline(TextBaseline::Bottom);
+            self.ctx.draw_text(&[offset,h-offset+tick+2].into(), "Y", 1);
         
         }else {
-            let pix_step =  (step * (w-2*offset) as f32 / (max_num - min_num))  as usize; 
 
             let mut curr_num = min_num;
-            for x in (offset..w).step_by(pix_step) {
-                let text_pos = Vec2D::new(x,offset - num_offset as usize);
+            // Numbers are centered under their grid line
+            self.ctx.set_text_align(TextAlign::Center);
+            self.ctx.set_text_baseline(TextBaseline::Top);
+            for x in (offset..=w.saturating_sub(offset)).step_by(pix_step) {
                 // Draw grid line
                 if x > offset {
-                    self.ctx.draw_line(&text_pos, &Vec2D::new(text_pos.x, h-offset), Hex::from_word("grey"));
+                    let _ = self.ctx.draw_line(&Vec2D::new(x, offset - tick), &Vec2D::new(x, h-offset), Hex::from_word("grey"));
                 }
-                self.ctx.draw_text(&text_pos, &curr_num.to_string(), 1);
+                self.ctx.draw_text(&Vec2D::new(x, label), &curr_num.to_string(), 1);
                 
                 curr_num += step;
             }
             // X axis
-            self.ctx.draw_line(&Vec2D::new(offset,offset), &Vec2D::new(w-offset, offset), 0x000000);
-            // X text
-            self.ctx.draw_text(&[w-offset/2,offset].into(), "X", 1);
+            self.ctx.set_line_dash(&[]);
+            let _ = self.ctx.draw_line(&Vec2D::new(offset,offset), &Vec2D::new(w-offset, offset), 0x000000);
+            // X text, right of the axis
+            self.ctx.set_text_align(TextAlign::Left);
+            self.ctx.set_text_baseline(TextBaseline::Middle);
+            self.ctx.draw_text(&[w-offset+tick+2,offset].into(), "X", 1);
         }
+        self.ctx.restore();
+        Ok(())
     }
 
 
     //------------------------------- Lerping
-    fn to_window_space(h:usize,w:usize,is_y_component: bool, n: f32, border_offsets: [f32;4], offset: usize) -> usize {
+    // Same as to_window_space, but without rounding to a pixel
+    fn to_window_space_f(h:usize,w:usize,is_y_component: bool, n: f32, border_offsets: [f32;4], offset: usize) -> f32 {
+        let [min_xnum, max_xnum, min_ynum, max_ynum] = border_offsets;
+        if is_y_component {
+            lerp(0.0, (h - 2*offset) as f32, invLerp(min_ynum, max_ynum, n)) + offset as f32
+        }else {
+            lerp(0.0, (w - 2*offset) as f32, invLerp(min_xnum, max_xnum, n)) + offset as f32
+        }
+    }
+    // Signed, so points left of or below the graph don't wrap around. Points far off the graph end up at the
+    // ends of isize (the cast saturates), so the offset is added saturating too.
+    fn to_window_space(h:usize,w:usize,is_y_component: bool, n: f32, border_offsets: [f32;4], offset: usize) -> isize {
         let [min_xnum, max_xnum, min_ynum, max_ynum] = border_offsets;
         if is_y_component {
-            let numerator = (n - min_ynum as f32) * (h - 2*offset) as f32;
-            let denominator = (max_ynum - min_ynum) as f32;
-            (numerator / denominator) as usize + offset
+            let numerator = (n - min_ynum) * (h - 2*offset) as f32;
+            let denominator = max_ynum - min_ynum;
+            ((numerator / denominator) as isize).saturating_add(offset as isize)
         }else {
-            let numerator = (n - min_xnum as f32) * (w - 2*offset) as f32;
-            let denominator = (max_xnum - min_xnum) as f32;
-            (numerator/ denominator) as usize + offset
+            let numerator = (n - min_xnum) * (w - 2*offset) as f32;
+            let denominator = max_xnum - min_xnum;
+            ((numerator/ denominator) as isize).saturating_add(offset as isize)
+        }
+    }
+
+    // There has to be room for the graph between the axes, which are offset pixels in from the edges of the screen.
+    // After this, sizes minus the offset (twice) can't go below zero.
+    fn check_offset(&self, offset: usize) -> Result<(), Error> {
+        for size in [self.ctx.w, self.ctx.h] {
+            if offset.saturating_mul(2) >= size {
+                return Err(Error::InvalidAxisOffset { offset, size });
+            }
+        }
+        Ok(())
+    }
+
+    // A graph can only be drawn if min..max is an actual range of numbers
+    fn check_range(min: f32, max: f32) -> Result<(), Error> {
+        if min < max && min.is_finite() && max.is_finite() {
+            Ok(())
+        }else {
+            Err(Error::InvalidGraphRange { min, max })
         }
     }
 
@@ -358,83 +1306,117 @@ impl<'a> GraphCtx<'a> {
     }
     //----------------------------------
 
+    // Keeps everything drawn inside the axes, offset pixels away from the edges of the screen
+    fn clip_to_frame(&mut self, offset: usize) {
+        let [w, h] = [self.ctx.w, self.ctx.h];
+        let size = |n: usize| (n + 1).saturating_sub(2 * offset) as f32;
+        self.ctx.clip_rect(offset as f32, offset as f32, size(w), size(h));
+    }
+
     // draw_graph
-    // This function keeps a copy of the previously plotted point, if the new point is too far away from the previous, 
-    // it will draw a line between the two (this is to remove the situation where it looks like the function is dotted) 
+    // The function is sampled every `step` pixels and the points are joined up into lines, so it never looks dotted.
+    // Wherever the function is undefined the line is broken, and continues once it comes back. When it goes off
+    // the graph the line is drawn up to the edge, and clipped to the axes so thick lines don't spill over them.
+    // The line is stroked like any other line, so it uses the ctx thickness, line joins and anti-aliasing.
 
     // step = How many times it steps, by default it steps by 1 meaening each pixel
-    pub fn draw_graph<F: Fn(f32) -> f32 >(&mut self, f: F, step: usize, offset: usize, min_xnum : f32, max_xnum: f32, min_ynum: f32, max_ynum: f32, color: u32 ) {
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw_graph<F: Fn(f32) -> f32 >(&mut self, f: F, step: usize, offset: usize, min_xnum : f32, max_xnum: f32, min_ynum: f32, max_ynum: f32, color: u32 ) -> Result<(), Error> {
+        self.check_offset(offset)?;
+        Self::check_range(min_xnum, max_xnum)?;
+        Self::check_range(min_ynum, max_ynum)?;
+        if step == 0 {
+            return Err(Error::InvalidStep { step: 0.0 });
+        }
         let h: usize = self.ctx.h; let w = self.ctx.w;
+        let border_offsets = [min_xnum, max_xnum, min_ynum, max_ynum];
 
-        let to_number_space = |is_y_component: bool, n: usize| -> f32 {
-            Self::to_number_space(h,w,is_y_component, n, [min_xnum, max_xnum, min_ynum,max_ynum], offset)
-        };
-        let to_window_space = |is_y_component: bool, n: f32| -> usize {
-            Self::to_window_space(h,w,is_y_component, n, [min_xnum, max_xnum, min_ynum,max_ynum], offset)
-        };
-
-        // Calculate initial point
-        // let init_x = to_number_space(false, offset);
-        // let wy = to_window_space( true, f(init_x));
-        // let mut prev_point: Vec2D<usize> = Vec2D::new(offset, wy);
-
-        let mut prev_point: Option< Vec2D<isize>  > = None;
+        let mut lines: Vec<(Vec<Vec2D<f32>>, bool)> = Vec::new();
+        let mut line: Vec<Vec2D<f32>> = Vec::new();
+        let mut prev: Option<(f32, f32)> = None; // the last sample, (window x, y)
+        let on_graph = |y: f32| y >= min_ynum && y <= max_ynum;
 
         for window_x in (offset..=(w - offset)).step_by(step) { 
             // Put x into number space
-            let x: f32 = to_number_space(false, window_x);
+            let x: f32 = Self::to_number_space(h, w, false, window_x, border_offsets, offset);
             let y: f32 = f(x);
-
-
-
-            if !(y.is_nan() || y > max_ynum || y < min_ynum) {
-                let wx: usize = window_x; let wy = to_window_space(true, y);
-
-                // println!("({x}, {y}) --> ({wx}, {wy})");
-                let point = Vec2D::new(wx as isize, wy as isize);
-
-                if let Some(pp) = &prev_point {
-                    if point.distance(pp) > 2 {
-                        let prev_thickness = self.ctx.thickness;
-                        self.ctx.set_thickness(prev_thickness *2);
-                        self.ctx.draw_line(&Vec2D::new(pp.x as usize, pp.y as usize), &Vec2D::new(point.x as usize, point.y as usize), color);
-                        self.ctx.set_thickness(prev_thickness);
-                    }else {
-                        let _ = self.ctx.draw_scaled_pixel(wx, wy, self.ctx.thickness, color);
-                    }
-                    prev_point = Some(point);
-                }else {
-                    let _ = self.ctx.draw_scaled_pixel(wx, wy, self.ctx.thickness , color);
-                    prev_point = Some(point);
+            let wx = window_x as f32;
+
+            // Where the function crosses the top or bottom of the graph between two samples
+            if let Some((prev_x, prev_y)) = prev {
+                if on_graph(y) != on_graph(prev_y) && !y.is_nan() && !prev_y.is_nan() {
+                    let outside = if on_graph(y) { prev_y } else { y };
+                    let edge = if outside > max_ynum { max_ynum } else { min_ynum };
+                    let t = (edge - prev_y) / (y - prev_y);
+                    let wy = Self::to_window_space_f(h, w, true, edge, border_offsets, offset);
+                    line.push(Vec2D::new(lerp(prev_x, wx, t), wy));
                 }
-
-            }else {
-                prev_point = None;
             }
-
-
-            
+            prev = Some((wx, y));
+
+            if on_graph(y) {
+                // The y position isn't rounded to a pixel, so anti-aliased curves don't wobble
+                let wy = Self::to_window_space_f(h, w, true, y, border_offsets, offset);
+                line.push(Vec2D::new(wx, wy));
+            }else if !line.is_empty() {
+                lines.push((self.ctx.to_device(&line), false));
+                line.clear();
+            }
+        }
+        if !line.is_empty() {
+            lines.push((self.ctx.to_device(&line), false));
         }
+
+        // All at once, so the joints between the lines aren't blended twice
+        self.ctx.save();
+        self.ctx.set_origin(Origin::BottomLeft);
+        self.clip_to_frame(offset);
+        self.ctx.stroke_lines(&lines, color, 1.0);
+        self.ctx.restore();
+        Ok(())
     }
 
     /// offset = the axis offset
     /// border_offsets = the ranges used in the plot  [xmin, xmax, ymin, ymax ]
-    pub fn plot_on_graph(&mut self, point: &Vec2D<f32>, scale:usize, color: u32) -> Result<(), String>{
+    /// 
+    /// Points outside of the graph are clipped off by the axes, it's only an error if they are outside of the screen.
+    pub fn plot_on_graph(&mut self, point: &Vec2D<f32>, scale:usize, color: u32) -> Result<(), Error>{
         let offset = self.settings.axis_offset;
         let border_offsets = self.settings.get_border_offsets();
+        let [min_xnum, max_xnum, min_ynum, max_ynum] = border_offsets;
+        self.check_offset(offset)?;
+        Self::check_range(min_xnum, max_xnum)?;
+        Self::check_range(min_ynum, max_ynum)?;
+        if !(point.x.is_finite() && point.y.is_finite()) {
+            return Ok(()); // nowhere to draw it, like undefined parts of draw_graph
+        }
 
         let h =self.ctx.h; let w = self.ctx.w;
         let new_x = Self::to_window_space(h, w, false, point.x, border_offsets, offset);
         let new_y = Self::to_window_space(h, w, true, point.y, border_offsets, offset);
         
-        self.ctx.draw_scaled_pixel(new_x, new_y, scale, color)
+        // Markers on the edge of the graph get cut off by the axes
+        self.ctx.save();
+        self.ctx.set_origin(Origin::BottomLeft);
+        self.clip_to_frame(offset);
+        let result = self.ctx.scaled_pixel(new_x, new_y, scale, color);
+        self.ctx.restore();
+        result
     }
 
-    pub fn plot_dataset(&mut self, points: &Vec<Vec<f32>>, scale: usize, color: u32) {
+    /// Plots every [x, y] point. Every point is drawn even if some fail, the first error is given back.
+    pub fn plot_dataset(&mut self, points: &Vec<Vec<f32>>, scale: usize, color: u32) -> Result<(), Error> {
+        let mut result = Ok(());
         for point in points {
-            let point = Vec2D::new(point[0] as f32, point[1] as f32);
-            self.plot_on_graph(&point, scale, color).unwrap();
+            let [x, y, ..] = point[..] else {
+                continue; // not a point
+            };
+            let plotted = self.plot_on_graph(&Vec2D::new(x, y), scale, color);
+            if result.is_ok() {
+                result = plotted;
+            }
         }
+        result
     }
 }
 //*----------------------------------------- */
@@ -445,8 +1427,37 @@ impl<'a> GraphCtx<'a> {
 
 
 /// Struct for red-green-b22232222lue-alpha pixels: [red,green,blue,alpha]
+/// 
+/// Every channel goes from 0 to 255, an alpha of 255 is fully opaque.
 pub struct Rgba( pub [u32;4] );
-///TODO: RGB to hex conversions
+impl Rgba {
+    pub fn new(r: u32, g: u32, b: u32, a: u32) -> Self {
+        Rgba([r, g, b, a])
+    }
+    /// Takes a hexadecimal color like 0xff0800 and gives it an alpha
+    pub fn from_hex(color: u32, alpha: u32) -> Self {
+        Rgba([(color >> 16) & 0xff, (color >> 8) & 0xff, color & 0xff, alpha])
+    }
+    /// The color without the alpha, as hexadecimal
+    pub fn to_hex(&self) -> u32 {
+        let [r, g, b, _] = self.0;
+        (r.min(255) << 16) | (g.min(255) << 8) | b.min(255)
+    }
+    /// The alpha from 0.0 to 1.0
+    pub fn alpha(&self) -> f32 {
+        self.0[3].min(255) as f32 / 255.0
+    }
+}
+
+/// Blends a color on top of another, source-over: result = src*alpha + dst*(1-alpha)
+pub fn blend(dst: u32, src: u32, alpha: f32) -> u32 {
+    let mix = |shift: u32| -> u32 {
+        let d = ((dst >> shift) & 0xff) as f32;
+        let s = ((src >> shift) & 0xff) as f32;
+        ((s * alpha + d * (1.0 - alpha)).round() as u32).min(255) << shift
+    };
+    mix(16) | mix(8) | mix(0)
+}
 
 
 pub struct Hex( pub u32);
@@ -461,7 +1472,8 @@ impl Hex {
             "green" => 0x5ced73,
 
             
-            "black" | _ => 0x000000,
+            _ => 0x000000, // black
+
         }
     }
     pub fn from_rgb(r:u8,g:u8,b:u8) -> u32 {
@@ -525,4 +1537,77 @@ impl Hex {
     //         error = error + dx;
     //         y+=sy;
     //     }
-    // }
\ No newline at end of file
+    // }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lit(ctx: &Ctx) -> usize {
+        ctx.buf.iter().filter(|&&p| p != 0).count()
+    }
+
+    #[test]
+    fn scaled_pixel_extremes() {
+        let mut ctx = Ctx::headless(10, 10);
+        for (x, y) in [(isize::MAX, 0), (isize::MIN, 0), (0, isize::MAX), (isize::MIN, isize::MIN), (isize::MAX, isize::MAX)] {
+            for scale in [1, 3, 1000] {
+                ctx.draw_scaled_pixel_i(x, y, scale, 0xffffff);
+                assert!(matches!(ctx.scaled_pixel(x, y, scale, 0xffffff), Err(Error::OutOfBounds { .. })));
+            }
+        }
+        assert!(ctx.draw_scaled_pixel(usize::MAX, 0, 3, 0xffffff).is_err());
+        assert_eq!(lit(&ctx), 0);
+
+        // Big enough to reach the screen from the end of isize, but most of it doesn't fit
+        assert!(ctx.scaled_pixel(isize::MAX, 5, usize::MAX, 0xffffff).is_err());
+        assert_eq!(lit(&ctx), 100);
+    }
+
+    #[test]
+    fn scaled_pixel_partly_off_screen() {
+        let mut ctx = Ctx::headless(10, 10);
+        assert!(ctx.draw_scaled_pixel(4, 4, 2, 0xffffff).is_ok());
+        assert_eq!(lit(&ctx), 9);
+
+        // Only the 2x4 pixels on the screen are drawn, from the bottom-left corner
+        let mut ctx = Ctx::headless(10, 10);
+        ctx.draw_scaled_pixel_i(-1, 1, 3, 0xffffff);
+        assert_eq!(lit(&ctx), 8);
+        assert!(ctx.scaled_pixel(-1, 1, 3, 0xffffff).is_err());
+        for (x, y) in [(0, 0), (1, 0), (0, 3), (1, 3)] {
+            assert_eq!(ctx.buf[(9 - y) * 10 + x], 0xffffff);
+        }
+        assert!(ctx.draw_scaled_pixel(9, 9, 2, 0xffffff).is_err());
+        assert_eq!(lit(&ctx), 8 + 4);
+    }
+
+    #[test]
+    fn plot_far_off_the_graph() {
+        let mut ctx = Ctx::headless(100, 100);
+        let mut graph = GraphCtx::new(&mut ctx, GraphSettings::new(10, 0.0, 10.0, 0.0, 10.0));
+        for point in [(1e30, 5.0), (5.0, 1e30), (-1e30, -1e30), (f32::MAX, f32::MIN)] {
+            let result = graph.plot_on_graph(&Vec2D::new(point.0, point.1), 3, 0xffffff);
+            assert!(matches!(result, Err(Error::OutOfBounds { .. })), "{point:?}");
+        }
+        let points = vec![vec![1e30, 5.0], vec![5.0, 5.0]];
+        assert!(graph.plot_dataset(&points, 1, 0xffffff).is_err());
+        // The point that is on the graph is still drawn
+        assert_eq!(lit(&ctx), 1);
+    }
+
+    #[test]
+    fn graph_errors() {
+        let mut ctx = Ctx::headless(100, 60);
+        let mut graph = GraphCtx::new(&mut ctx, GraphSettings::new(30, 0.0, 10.0, 0.0, 10.0));
+        // Fits across, but not up
+        assert!(matches!(graph.draw_axis(false, 1.0, 0.0, 10.0), Err(Error::InvalidAxisOffset { offset: 30, size: 60 })));
+        assert!(matches!(graph.plot_on_graph(&Vec2D::new(5.0, 5.0), 1, 0), Err(Error::InvalidAxisOffset { offset: 30, size: 60 })));
+        assert!(matches!(graph.draw_graph(|x| x, 1, 120, 0.0, 10.0, 0.0, 10.0, 0), Err(Error::InvalidAxisOffset { offset: 120, size: 100 })));
+
+        let mut graph = GraphCtx::new(&mut ctx, GraphSettings::new(10, 0.0, 10.0, 0.0, 10.0));
+        assert!(matches!(graph.draw_graph(|x| x, 0, 10, 0.0, 10.0, 0.0, 10.0, 0), Err(Error::InvalidStep { .. })));
+        assert!(matches!(graph.draw_axis(true, 0.001, 0.0, 10.0), Err(Error::InvalidStep { step: 0.001 })));
+        assert!(matches!(graph.draw_axis(true, 1.0, 10.0, 0.0), Err(Error::InvalidGraphRange { min: 10.0, max: 0.0 })));
+        assert!(graph.draw_axis(true, 1.0, 0.0, 10.0).is_ok());
+    }
+}
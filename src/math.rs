@@ -61,6 +61,16 @@ impl Vec2D<f32> {  // Bonus functions for real numbers
     }
     
 }
+impl Vec2D<usize> {
+    pub fn f32(&self) -> Vec2D<f32> {
+        Vec2D::new(self.x as f32, self.y as f32)
+    }
+}
+impl Vec2D<isize> {
+    pub fn f32(&self) -> Vec2D<f32> {
+        Vec2D::new(self.x as f32, self.y as f32)
+    }
+}
 
 
 
@@ -96,11 +106,94 @@ impl<T: Copy> From<Vec<T>> for Vec2D<T> {
 // }
 
 
+/// A 2D affine transformation, the same matrix as the js canvas uses:
+/// ```text
+/// | a c e |
+/// | b d f |
+/// | 0 0 1 |
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transform {
+    pub a: f32, pub b: f32,
+    pub c: f32, pub d: f32,
+    pub e: f32, pub f: f32,
+}
+impl Default for Transform {
+    fn default() -> Self {
+        Transform::identity()
+    }
+}
+impl Transform {
+    pub fn new(a: f32, b: f32, c: f32, d: f32, e: f32, f: f32) -> Self {
+        Transform { a, b, c, d, e, f }
+    }
+    /// The transform that doesn't change anything
+    pub fn identity() -> Self {
+        Transform::new(1.0, 0.0, 0.0, 1.0, 0.0, 0.0)
+    }
+    pub fn translation(x: f32, y: f32) -> Self {
+        Transform::new(1.0, 0.0, 0.0, 1.0, x, y)
+    }
+    /// Rotation counter-clockwise by t radians (with y going up)
+    pub fn rotation(t: f32) -> Self {
+        Transform::new(t.cos(), t.sin(), -t.sin(), t.cos(), 0.0, 0.0)
+    }
+    pub fn scaling(x: f32, y: f32) -> Self {
+        Transform::new(x, 0.0, 0.0, y, 0.0, 0.0)
+    }
+
+    /// self * other, so `other` is applied to points first
+    pub fn multiply(&self, other: &Transform) -> Transform {
+        Transform {
+            a: self.a * other.a + self.c * other.b,
+            b: self.b * other.a + self.d * other.b,
+            c: self.a * other.c + self.c * other.d,
+            d: self.b * other.c + self.d * other.d,
+            e: self.a * other.e + self.c * other.f + self.e,
+            f: self.b * other.e + self.d * other.f + self.f,
+        }
+    }
+
+    pub fn apply(&self, p: &Vec2D<f32>) -> Vec2D<f32> {
+        Vec2D::new(self.a * p.x + self.c * p.y + self.e, self.b * p.x + self.d * p.y + self.f)
+    }
+
+    /// None if the transform squashes everything flat (e.g. a scale of 0)
+    pub fn invert(&self) -> Option<Transform> {
+        let det = self.a * self.d - self.b * self.c;
+        if det == 0.0 || !det.is_finite() {
+            return None;
+        }
+        Some(Transform {
+            a: self.d / det,
+            b: -self.b / det,
+            c: -self.c / det,
+            d: self.a / det,
+            e: (self.c * self.f - self.d * self.e) / det,
+            f: (self.b * self.e - self.a * self.f) / det,
+        })
+    }
+
+    pub fn is_identity(&self) -> bool {
+        *self == Transform::identity()
+    }
+    /// Only moves things around, no scaling or rotation
+    pub fn is_translation(&self) -> bool {
+        self.a == 1.0 && self.b == 0.0 && self.c == 0.0 && self.d == 1.0
+    }
+    /// How much lengths get scaled on average, used for line thickness
+    pub fn scale_factor(&self) -> f32 {
+        (self.a * self.d - self.b * self.c).abs().sqrt()
+    }
+}
+
+
 pub fn lerp(a: f32,b:f32 ,t: f32) -> f32  {
     a + (b-a)*t
 }
 
 // c= a + t(b-a)
+#[allow(non_snake_case)]
 pub fn invLerp(a: f32, b: f32, c: f32) -> f32 {
     (c-a)/(b-a)
 }
@@ -132,13 +225,13 @@ impl Segment {
         let x_t = invLerp(self.start.x, self.end.x, point.x);
         let y_t = invLerp(self.start.y, self.end.y, point.y);
         
-        (x_t >= 0.0 && x_t <= 1.0) && (y_t >= 0.0 && y_t <= 1.0)
+        (0.0..=1.0).contains(&x_t) && (0.0..=1.0).contains(&y_t)
     }
 
     // If there is an intersection it returns the t values, 
     // Otherwise (if lines are parallel) it returns None
     pub fn get_t(&self, other: &Segment) -> Option<(f32,f32)> {
-        let l1 = Segment::to_line(&self);
+        let l1 = Segment::to_line(self);
         let l2 = Segment::to_line(other);
 
         
@@ -153,7 +246,7 @@ impl Segment {
                 None
             }
         }else {
-            return None;
+            None
         }
     }
 
@@ -189,11 +282,7 @@ impl Line {
         ]);
         let c = vec![self.c, other.c];
 
-        if let Some(solution) = m.cramers_solve(&c) {
-            return Some(Vec2D::new(solution[0], solution[1]))
-        }else {
-            None
-        }
+        m.cramers_solve(&c).map(|solution| Vec2D::new(solution[0], solution[1]))
     }
 }
 
@@ -216,9 +305,9 @@ impl Matrix {
         let arr: Vec<Vec<f32>> = self.arr.iter().map(
             |row| { 
                 let mut new_row: Vec<f32> = Vec::with_capacity(m-1);
-                for col_idx in 0..m {
+                for (col_idx, val) in row.iter().enumerate() {
                     if col_idx != i {
-                        new_row.push(row[col_idx]);
+                        new_row.push(*val);
                     }
                 }
                 new_row
@@ -240,17 +329,17 @@ impl Matrix {
         Matrix::new(new_row)
     }
     // Replaces collumn to the matrix, in the location i
-    pub fn replace_col(&self, i: usize, vec: &Vec<f32>) -> Matrix {
+    pub fn replace_col(&self, i: usize, vec: &[f32]) -> Matrix {
         assert!(vec.len() == self.arr.len());
         let n:usize = self.arr.len(); let m: usize = self.arr[0].len();
         let mut new_arr: Vec<Vec<f32>> = vec![vec![0. ; m] ; n];
 
-        for row in 0..n{
-            for col in 0..m {
+        for (row, new_row) in new_arr.iter_mut().enumerate() {
+            for (col, element) in new_row.iter_mut().enumerate() {
                 if col == i {
-                    new_arr[row][col] = vec[row];
+                    *element = vec[row];
                 }else {
-                    new_arr[row][col] = self.arr[row][col];
+                    *element = self.arr[row][col];
                 }
             }
         }
@@ -291,7 +380,7 @@ impl Matrix {
     pub fn mut_mult_scalar(&mut self, v: f32) {
         for row in &mut self.arr {
             for element in row {
-                *element = *element * v;
+                *element *= v;
             }
         }
     }
@@ -300,7 +389,7 @@ impl Matrix {
     // Assumes:
     // 1) this is a square matrix, and x,c are the correct size.
     // 2) This matrix is inversible : |M| /= 0 
-    pub fn cramers_solve(&self, c: &Vec<f32>) -> Option<Vec<f32>> {
+    pub fn cramers_solve(&self, c: &[f32]) -> Option<Vec<f32>> {
         let n:usize = self.arr.len(); let m:usize = self.arr[0].len();
         let correct_dimentions:bool = n== m && n == c.len();
         if !correct_dimentions {return None;}
@@ -310,10 +399,10 @@ impl Matrix {
 
         let mut solution:Vec<f32> = vec![0.; n];
 
-        for j in 0..m {
+        for (j, sol) in solution.iter_mut().enumerate().take(m) {
             let det_c = self.replace_col(j, c, ).det();
 
-            solution[j] = det_c / det_m;
+            *sol = det_c / det_m;
         }
 
         Some(solution)
@@ -0,0 +1,149 @@
+// Paths like in the js canvas. Curves are flattened into short lines as soon as they are added,
+// so stroking and filling a path is the same as stroking and filling polygons.
+
+use std::f32::consts::PI;
+
+use crate::math::Vec2D;
+use crate::stroke;
+
+// How far (in pixels) a flattened curve is allowed to be from the real curve
+const TOLERANCE: f32 = 0.25;
+
+/// A list of shapes (sub-paths) built from lines and curves.
+#[derive(Clone, Debug, Default)]
+pub struct Path {
+    subpaths: Vec<SubPath>,
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct SubPath {
+    pub points: Vec<Vec2D<f32>>,
+    pub closed: bool,
+}
+
+impl Path {
+    pub fn new() -> Self {
+        Path { subpaths: Vec::new() }
+    }
+
+    pub(crate) fn subpaths(&self) -> &[SubPath] {
+        &self.subpaths
+    }
+
+    pub(crate) fn current_point(&self) -> Option<&Vec2D<f32>> {
+        self.subpaths.last().and_then(|s| s.points.last())
+    }
+
+    // Adds a point to the last sub-path, or starts a new one if there isn't one
+    fn push(&mut self, point: Vec2D<f32>) {
+        match self.subpaths.last_mut() {
+            Some(sub) if !sub.closed => sub.points.push(point),
+            _ => self.move_to(point.x, point.y),
+        }
+    }
+
+    /// Starts a new sub-path at (x, y)
+    pub fn move_to(&mut self, x: f32, y: f32) {
+        self.subpaths.push(SubPath { points: vec![Vec2D::new(x, y)], closed: false });
+    }
+
+    /// Adds a straight line from the current point to (x, y)
+    pub fn line_to(&mut self, x: f32, y: f32) {
+        self.push(Vec2D::new(x, y));
+    }
+
+    /// Adds a curve to (x, y), pulled towards the control point (cpx, cpy)
+    pub fn quadratic_curve_to(&mut self, cpx: f32, cpy: f32, x: f32, y: f32) {
+        let Some(p0) = self.current_point().cloned() else {
+            return self.move_to(x, y);
+        };
+        let (p1, p2) = (Vec2D::new(cpx, cpy), Vec2D::new(x, y));
+
+        // The error of n segments is at most |p0 - 2p1 + p2| / (4n²)
+        let dd = p0.sub_vec(&p1.mult_scalar(2.0)).add_vec(&p2).magnitude().sqrt();
+        let n = ((dd / (4.0 * TOLERANCE)).sqrt().ceil() as usize).clamp(1, 500);
+        for i in 1..=n {
+            let t = i as f32 / n as f32;
+            let mt = 1.0 - t;
+            self.push(Vec2D::new(
+                mt * mt * p0.x + 2.0 * mt * t * p1.x + t * t * p2.x,
+                mt * mt * p0.y + 2.0 * mt * t * p1.y + t * t * p2.y,
+            ));
+        }
+    }
+
+    /// Adds a cubic bézier curve to (x, y), with the control points (cp1x, cp1y) and (cp2x, cp2y)
+    pub fn bezier_curve_to(&mut self, cp1x: f32, cp1y: f32, cp2x: f32, cp2y: f32, x: f32, y: f32) {
+        let Some(p0) = self.current_point().cloned() else {
+            return self.move_to(x, y);
+        };
+        let (p1, p2, p3) = (Vec2D::new(cp1x, cp1y), Vec2D::new(cp2x, cp2y), Vec2D::new(x, y));
+
+        // The error of n segments is at most 3 * max|p[i] - 2p[i+1] + p[i+2]| / (4n²)
+        let dd1 = p0.sub_vec(&p1.mult_scalar(2.0)).add_vec(&p2).magnitude().sqrt();
+        let dd2 = p1.sub_vec(&p2.mult_scalar(2.0)).add_vec(&p3).magnitude().sqrt();
+        let n = ((3.0 * dd1.max(dd2) / (4.0 * TOLERANCE)).sqrt().ceil() as usize).clamp(1, 500);
+        for i in 1..=n {
+            let t = i as f32 / n as f32;
+            let mt = 1.0 - t;
+            let [a, b, c, d] = [mt * mt * mt, 3.0 * mt * mt * t, 3.0 * mt * t * t, t * t * t];
+            self.push(Vec2D::new(
+                a * p0.x + b * p1.x + c * p2.x + d * p3.x,
+                a * p0.y + b * p1.y + c * p2.y + d * p3.y,
+            ));
+        }
+    }
+
+    /// Adds a circular arc around (x, y), going from start_angle to end_angle (radians, counter-clockwise).
+    /// A line is added from the current point to the start of the arc.
+    pub fn arc(&mut self, x: f32, y: f32, radius: f32, start_angle: f32, end_angle: f32) {
+        for point in stroke::arc(&Vec2D::new(x, y), radius, radius, start_angle, end_angle, 1.0) {
+            self.push(point);
+        }
+    }
+
+    /// Adds a rounded corner: an arc with the radius given, touching both the line from the current point to
+    /// (x1, y1) and the line from (x1, y1) to (x2, y2). A straight line is added up to the start of the arc.
+    pub fn arc_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, radius: f32) {
+        let Some(p0) = self.current_point().cloned() else {
+            return self.move_to(x1, y1);
+        };
+        let (p1, p2) = (Vec2D::new(x1, y1), Vec2D::new(x2, y2));
+
+        let v0 = p0.sub_vec(&p1).normalize();
+        let v1 = p2.sub_vec(&p1).normalize();
+        let cross = v0.x * v1.y - v0.y * v1.x;
+        if radius <= 0.0 || cross.abs() < 1e-6 || p0.distance(&p1) < 1e-6 || p2.distance(&p1) < 1e-6 {
+            // No corner to round off
+            return self.line_to(x1, y1);
+        }
+
+        let angle = v0.dot(&v1).clamp(-1.0, 1.0).acos(); // angle of the corner
+        let tangent_dist = radius / (angle / 2.0).tan();
+        let center_dist = radius / (angle / 2.0).sin();
+        let start = p1.add_vec(&v0.mult_scalar(tangent_dist));
+        let end = p1.add_vec(&v1.mult_scalar(tangent_dist));
+        let center = p1.add_vec(&v0.add_vec(&v1).normalize().mult_scalar(center_dist));
+
+        let start_angle = (start.y - center.y).atan2(start.x - center.x);
+        let mut end_angle = (end.y - center.y).atan2(end.x - center.x);
+        // Go the short way around, the direction depends on which way the corner turns
+        if cross < 0.0 {
+            if end_angle < start_angle { end_angle += 2.0 * PI }
+        }else if end_angle > start_angle {
+            end_angle -= 2.0 * PI
+        }
+        self.arc(center.x, center.y, radius, start_angle, end_angle);
+    }
+
+    /// Joins the current point back to the start of the sub-path, anything added after starts a new one.
+    pub fn close_path(&mut self) {
+        if let Some(sub) = self.subpaths.last_mut() {
+            if !sub.closed {
+                sub.closed = true;
+                let start = sub.points[0].clone();
+                self.subpaths.push(SubPath { points: vec![start], closed: false });
+            }
+        }
+    }
+}
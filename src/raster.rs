@@ -0,0 +1,257 @@
+// Rasterising helpers used by Ctx. They don't touch any buffer, they just produce pixels
+// with how much of the pixel is covered (0.0 to 1.0), so Ctx can blend them.
+
+use crate::math::Vec2D;
+
+/// (x, y, coverage)
+pub(crate) type Coverage = Vec<(i32, i32, f32)>;
+
+/// Xiaolin Wu's line algorithm, integer coordinates are the centers of pixels.
+///
+/// Unlike the textbook version the end points aren't faded out, otherwise every joint in a
+/// polyline (like a function plot made of many short segments) would have a dim pixel.
+pub(crate) fn wu_line(mut x0: f32, mut y0: f32, mut x1: f32, mut y1: f32, out: &mut Coverage) {
+    let steep = (y1 - y0).abs() > (x1 - x0).abs();
+    if steep {
+        std::mem::swap(&mut x0, &mut y0);
+        std::mem::swap(&mut x1, &mut y1);
+    }
+    if x0 > x1 {
+        std::mem::swap(&mut x0, &mut x1);
+        std::mem::swap(&mut y0, &mut y1);
+    }
+
+    let dx = x1 - x0;
+    let gradient = if dx == 0.0 { 1.0 } else { (y1 - y0) / dx };
+
+    // Swaps x and y back when the line is steep
+    let mut plot = |x: i32, y: i32, c: f32| {
+        if c > 0.0 {
+            if steep { out.push((y, x, c)) } else { out.push((x, y, c)) }
+        }
+    };
+
+    let x_start = x0.round() as i32;
+    let x_end = x1.round() as i32;
+    let mut intery = y0 + gradient * (x_start as f32 - x0);
+
+    for x in x_start..=x_end {
+        let y = intery.floor();
+        let frac = intery - y;
+        plot(x, y as i32, 1.0 - frac);
+        plot(x, y as i32 + 1, frac);
+        intery += gradient;
+    }
+}
+
+/// Cuts the part of the line a->b that is on the screen (with a pixel to spare), so lines going way off screen
+/// don't get walked pixel by pixel. None if none of it is on the screen.
+pub(crate) fn clip_segment(a: &Vec2D<f32>, b: &Vec2D<f32>, (width, height): (usize, usize)) -> Option<(Vec2D<f32>, Vec2D<f32>)> {
+    let (t0, t1) = clip_range(a, b, [-1.0, -1.0, width as f32, height as f32])?;
+    let (dx, dy) = (b.x as f64 - a.x as f64, b.y as f64 - a.y as f64);
+    let at = |t: f64| Vec2D::new((a.x as f64 + t * dx) as f32, (a.y as f64 + t * dy) as f32);
+    Some((at(t0), at(t1)))
+}
+
+/// Where the line a->b goes into and out of the rect [min x, min y, max x, max y], with t going from 0 at a
+/// to 1 at b. None if it misses the rect. It's worked out in f64, so long lines still get cut precisely.
+pub(crate) fn clip_range(a: &Vec2D<f32>, b: &Vec2D<f32>, [min_x, min_y, max_x, max_y]: [f32; 4]) -> Option<(f64, f64)> {
+    if !(a.x.is_finite() && a.y.is_finite() && b.x.is_finite() && b.y.is_finite()) {
+        return None;
+    }
+    // Liang-Barsky: every side of the rect cuts off one end
+    let (ax, ay) = (a.x as f64, a.y as f64);
+    let (dx, dy) = (b.x as f64 - ax, b.y as f64 - ay);
+    let (mut t0, mut t1) = (0.0f64, 1.0f64);
+    for (p, q) in [(-dx, ax - min_x as f64), (dx, max_x as f64 - ax), (-dy, ay - min_y as f64), (dy, max_y as f64 - ay)] {
+        if p == 0.0 {
+            if q < 0.0 { return None } // parallel to the side and outside of it
+            continue;
+        }
+        let t = q / p;
+        if p < 0.0 {
+            if t > t1 { return None }
+            t0 = t0.max(t);
+        }else {
+            if t < t0 { return None }
+            t1 = t1.min(t);
+        }
+    }
+    Some((t0, t1))
+}
+
+/// How to decide which parts of a shape are inside when its outline crosses itself, like the fill rules in js.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FillRule {
+    /// Inside if the outline winds around the point at all
+    NonZero,
+    /// Inside if a ray from the point crosses the outline an odd number of times, so overlaps become holes
+    EvenOdd,
+}
+
+struct Edge {
+    x0: f32, y0: f32,
+    x1: f32, y1: f32, // y0 < y1
+    dir: i32, // +1 if the edge goes up, -1 if it goes down
+}
+
+// How many scanlines are sampled per pixel row when anti-aliasing
+const AA_SAMPLES: usize = 4;
+
+/// Scanline fill of polygons, every contour is closed automatically.
+///
+/// Pixel centers are at integer coordinates, only pixels inside (0,0)..(width,height) are returned.
+/// With anti-aliasing each row is sampled several times and the edges get partial coverage.
+pub(crate) fn fill_polygons(contours: &[Vec<Vec2D<f32>>], rule: FillRule, anti_aliasing: bool, (width, height): (usize, usize)) -> Coverage {
+    let mut edges: Vec<Edge> = Vec::new();
+    let [mut min_x, mut min_y, mut max_x, mut max_y] = [f32::MAX, f32::MAX, f32::MIN, f32::MIN];
+
+    for contour in contours {
+        for (i, a) in contour.iter().enumerate() {
+            let b = &contour[(i + 1) % contour.len()];
+            min_x = min_x.min(a.x); max_x = max_x.max(a.x);
+            min_y = min_y.min(a.y); max_y = max_y.max(a.y);
+
+            if a.y == b.y || !(a.y.is_finite() && b.y.is_finite() && a.x.is_finite() && b.x.is_finite()) {
+                continue; // horizontal edges never cross a scanline
+            }
+            if a.y < b.y {
+                edges.push(Edge { x0: a.x, y0: a.y, x1: b.x, y1: b.y, dir: 1 });
+            }else {
+                edges.push(Edge { x0: b.x, y0: b.y, x1: a.x, y1: a.y, dir: -1 });
+            }
+        }
+    }
+    let mut pixels: Coverage = Vec::new();
+    if edges.is_empty() || width == 0 || height == 0 {
+        return pixels;
+    }
+    edges.sort_by(|a, b| a.y0.total_cmp(&b.y0));
+
+    // Saturating, shapes can be far off the screen
+    let first_row = (min_y.floor() as i32).saturating_sub(1).max(0);
+    let last_row = (max_y.ceil() as i32).saturating_add(1).min(height as i32 - 1);
+    let first_col = (min_x.floor() as i32).saturating_sub(1).max(0);
+    let last_col = (max_x.ceil() as i32).saturating_add(1).min(width as i32 - 1);
+    if first_row > last_row || first_col > last_col {
+        return pixels;
+    }
+
+    let samples = if anti_aliasing { AA_SAMPLES } else { 1 };
+    let weight = 1.0 / samples as f32;
+    let mut row: Vec<f32> = vec![0.0; (last_col - first_col + 1) as usize];
+    let mut active: Vec<&Edge> = Vec::new();
+    let mut crossings: Vec<(f32, i32)> = Vec::new();
+    let mut next_edge = 0;
+
+    for y in first_row..=last_row {
+        row.iter_mut().for_each(|c| *c = 0.0);
+
+        for k in 0..samples {
+            let sy = if anti_aliasing { y as f32 - 0.5 + (k as f32 + 0.5) * weight } else { y as f32 };
+
+            // Scanlines only go up, so edges can be added and dropped as we go
+            while next_edge < edges.len() && edges[next_edge].y0 <= sy {
+                active.push(&edges[next_edge]);
+                next_edge += 1;
+            }
+            active.retain(|e| e.y1 > sy);
+
+            crossings.clear();
+            for e in active.iter().filter(|e| e.y0 <= sy) {
+                crossings.push((e.x0 + (sy - e.y0) * (e.x1 - e.x0) / (e.y1 - e.y0), e.dir));
+            }
+            crossings.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+            let mut winding = 0;
+            for pair in crossings.windows(2) {
+                winding += pair[0].1;
+                let inside = match rule {
+                    FillRule::NonZero => winding != 0,
+                    FillRule::EvenOdd => winding % 2 != 0,
+                };
+                if inside {
+                    add_span(&mut row, first_col, pair[0].0, pair[1].0, anti_aliasing, weight);
+                }
+            }
+        }
+
+        for (i, c) in row.iter().enumerate() {
+            if *c > 0.0 {
+                pixels.push((first_col + i as i32, y, c.min(1.0)));
+            }
+        }
+    }
+    pixels
+}
+
+// Adds the span [xa, xb) to the row. Without anti-aliasing a pixel is either in or out depending on its center,
+// otherwise it gets the part of the pixel that the span covers.
+fn add_span(row: &mut [f32], first_col: i32, xa: f32, xb: f32, anti_aliasing: bool, weight: f32) {
+    let last_col = first_col + row.len() as i32 - 1;
+    if anti_aliasing {
+        let xa = xa.max(first_col as f32 - 0.5);
+        let xb = xb.min(last_col as f32 + 0.5);
+        if xb <= xa { return }
+
+        let ia = (xa + 0.5).floor() as i32;
+        let ib = ((xb + 0.5).floor() as i32).min(last_col);
+        if ia == ib {
+            row[(ia - first_col) as usize] += (xb - xa) * weight;
+        }else {
+            row[(ia - first_col) as usize] += (ia as f32 + 0.5 - xa) * weight;
+            for i in ia + 1..ib {
+                row[(i - first_col) as usize] += weight;
+            }
+            row[(ib - first_col) as usize] += (xb - (ib as f32 - 0.5)) * weight;
+        }
+    }else {
+        let ia = (xa.ceil() as i32).max(first_col);
+        let ib = (xb.ceil() as i32 - 1).min(last_col);
+        for i in ia..=ib {
+            row[(i - first_col) as usize] = 1.0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn coverage_at(pixels: &Coverage, x: i32, y: i32) -> f32 {
+        pixels.iter().filter(|p| (p.0, p.1) == (x, y)).map(|p| p.2).sum()
+    }
+
+    // A pentagram around (50, 50), drawn in one go so the middle is wound around twice
+    fn star() -> Vec<Vec<Vec2D<f32>>> {
+        let points = (0..5).map(|i| {
+            let angle = std::f32::consts::FRAC_PI_2 + i as f32 * 4.0 * std::f32::consts::PI / 5.0;
+            Vec2D::new(50.0 + 40.0 * angle.cos(), 50.0 + 40.0 * angle.sin())
+        });
+        vec![points.collect()]
+    }
+
+    #[test]
+    fn star_fill_rules() {
+        for anti_aliasing in [false, true] {
+            let non_zero = fill_polygons(&star(), FillRule::NonZero, anti_aliasing, (100, 100));
+            let even_odd = fill_polygons(&star(), FillRule::EvenOdd, anti_aliasing, (100, 100));
+            // The middle is a hole with EvenOdd only
+            assert_eq!(coverage_at(&non_zero, 50, 50), 1.0);
+            assert_eq!(coverage_at(&even_odd, 50, 50), 0.0);
+            // The points are inside once, the same with both
+            assert_eq!(coverage_at(&non_zero, 50, 80), 1.0);
+            assert_eq!(coverage_at(&even_odd, 50, 80), 1.0);
+            // Outside of the star
+            assert_eq!(coverage_at(&non_zero, 5, 95), 0.0);
+            assert_eq!(coverage_at(&even_odd, 5, 95), 0.0);
+        }
+    }
+
+    #[test]
+    fn fill_off_screen() {
+        // Only the pixels on the screen come back, even when the shape is huge
+        let far = vec![vec![Vec2D::new(-1e9, -1e9), Vec2D::new(1e9, -1e9), Vec2D::new(1e9, 1e9)]];
+        assert!(fill_polygons(&far, FillRule::NonZero, true, (10, 10)).iter().all(|p| (0..10).contains(&p.0) && (0..10).contains(&p.1)));
+    }
+}
@@ -0,0 +1,266 @@
+// Recording what's drawn on a Ctx, so it can be drawn again later on another Ctx (a different size, or an SVG)
+// without running whatever made the drawing again.
+// The shapes are recorded where they are about to be drawn (already transformed), along with the settings
+// they were drawn with, so everything that draws (including GraphCtx) gets recorded.
+
+use crate::gradient::Gradient;
+use crate::image::{Image, ImageSource};
+use crate::math::{Transform, Vec2D};
+use crate::{Ctx, FillRule, Rgba, State};
+
+/// Everything drawn on a Ctx between `begin_recording` and `end_recording`, see `replay`.
+#[derive(Clone, Default)]
+pub struct DisplayList {
+    commands: Vec<Command>,
+}
+
+#[derive(Clone)]
+pub(crate) enum Command {
+    Clear(u32),
+    ClearTransparent,
+    Save,
+    Restore,
+    Clip { contours: Vec<Vec<Vec2D<f32>>>, rule: FillRule, anti_aliasing: bool, state: State },
+    Stroke { lines: Vec<(Vec<Vec2D<f32>>, bool)>, color: u32, alpha: f32, state: State },
+    Fill { contours: Vec<Vec<Vec2D<f32>>>, rule: FillRule, anti_aliasing: bool, color: u32, alpha: f32, state: State },
+    Gradient { contours: Vec<Vec<Vec2D<f32>>>, rule: FillRule, anti_aliasing: bool, gradient: Gradient, state: State },
+    // Text and images are kept in the coordinates they were drawn with, they go through state.transform
+    Text { pos: Vec2D<usize>, text: String, scale: usize, color: u32, alpha: f32, state: State },
+    Image { image: Image, pos: Vec2D<f32>, size: [f32; 2], state: State },
+}
+
+impl DisplayList {
+    pub fn len(&self) -> usize {
+        self.commands.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+
+    /// Draws everything that was recorded onto ctx, with the same colors, thickness, text scale and so on.
+    /// The transform is applied on top of everything, e.g. `Transform::scaling(2.0, 2.0)` to draw it twice as big.
+    /// The settings of ctx are the same afterwards.
+    /// ```
+    /// # use bml_grapher::{Ctx, math::Transform};
+    /// let mut ctx = Ctx::headless(320, 240);
+    /// ctx.begin_recording();
+    /// ctx.clear_rect(0xffffff);
+    /// ctx.draw_line(&[10,10].into(), &[300,200].into(), 0xff0800).unwrap();
+    /// let list = ctx.end_recording();
+    ///
+    /// let mut big = Ctx::headless(640, 480);
+    /// list.replay(&mut big, Some(Transform::scaling(2.0, 2.0)));
+    /// ```
+    pub fn replay(&self, ctx: &mut Ctx, transform: Option<Transform>) {
+        let transform = transform.unwrap_or_else(Transform::identity);
+        let apply = |shapes: &[Vec<Vec2D<f32>>]| -> Vec<Vec<Vec2D<f32>>> {
+            shapes.iter().map(|shape| shape.iter().map(|p| transform.apply(p)).collect()).collect()
+        };
+
+        ctx.save();
+        let depth = ctx.saved.len();
+        for command in &self.commands {
+            match command {
+                Command::Clear(color) => ctx.clear_rect(*color),
+                Command::ClearTransparent => ctx.clear_transparent(),
+                Command::Save => ctx.save(),
+                // Only what was saved during the replay can be restored
+                Command::Restore => if ctx.saved.len() > depth { ctx.restore() },
+                Command::Clip { contours, rule, anti_aliasing, state } => {
+                    ctx.use_state(state, &transform);
+                    ctx.clip_shapes(&apply(contours), *rule, *anti_aliasing);
+                },
+                Command::Stroke { lines, color, alpha, state } => {
+                    ctx.use_state(state, &transform);
+                    let lines: Vec<_> = lines.iter()
+                        .map(|(line, closed)| (line.iter().map(|p| transform.apply(p)).collect(), *closed))
+                        .collect();
+                    ctx.stroke_lines(&lines, *color, *alpha);
+                },
+                Command::Fill { contours, rule, anti_aliasing, color, alpha, state } => {
+                    ctx.use_state(state, &transform);
+                    ctx.fill_shapes(&apply(contours), *rule, *anti_aliasing, *color, *alpha);
+                },
+                Command::Gradient { contours, rule, anti_aliasing, gradient, state } => {
+                    ctx.use_state(state, &transform);
+                    ctx.fill_shapes_gradient(&apply(contours), *rule, *anti_aliasing, gradient);
+                },
+                Command::Text { pos, text, scale, color, alpha, state } => {
+                    ctx.use_state(state, &transform);
+                    ctx.draw_text_rgba(pos, text, *scale, &Rgba::from_hex(*color, (alpha * 255.0).round() as u32));
+                },
+                Command::Image { image, pos, size, state } => {
+                    ctx.use_state(state, &transform);
+                    ctx.draw_image_scaled(image, pos, size[0], size[1]);
+                },
+            }
+        }
+        // Anything that was saved and never restored is thrown away
+        ctx.saved.truncate(depth);
+        ctx.restore();
+    }
+}
+
+impl Ctx {
+    /// Starts recording everything that is drawn (it's still drawn as usual), until end_recording.
+    /// Starting again throws away what was recorded so far.
+    pub fn begin_recording(&mut self) {
+        self.recording = Some(DisplayList::default());
+    }
+    /// Stops recording and gives back what was drawn since begin_recording (nothing if it wasn't recording).
+    pub fn end_recording(&mut self) -> DisplayList {
+        self.recording.take().unwrap_or_default()
+    }
+    pub fn is_recording(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    // Adds a command to the recording, it's only made if something is recording
+    pub(crate) fn record(&mut self, command: impl FnOnce(&Self) -> Command) {
+        if self.recording.is_some() {
+            let command = command(self);
+            if let Some(recording) = &mut self.recording {
+                recording.commands.push(command);
+            }
+        }
+    }
+
+    // Copies the part of the image that's drawn, so the recording doesn't have to hold on to it
+    pub(crate) fn record_image<I: ImageSource + ?Sized>(&mut self, src: &I, [sx, sy, sw, sh]: [usize; 4], pos: &Vec2D<f32>, size: [f32; 2]) {
+        self.record(|ctx| {
+            let mut image = Image::new(Vec::with_capacity(sw * sh), sw, sh);
+            let mut alpha = Vec::with_capacity(sw * sh);
+            for y in sy..sy + sh {
+                for x in sx..sx + sw {
+                    image.buf.push(src.pixel(x, y));
+                    alpha.push((src.alpha(x, y) * 255.0).round() as u8);
+                }
+            }
+            if alpha.iter().any(|a| *a < 255) {
+                image.alpha = Some(alpha);
+            }
+            Command::Image { image, pos: pos.clone(), size, state: ctx.state.clone() }
+        });
+    }
+
+    // The settings of a recorded command, without its clip (the replayed clips are used instead)
+    fn use_state(&mut self, state: &State, transform: &Transform) {
+        let (clip, svg_clip) = (self.state.clip.take(), self.state.svg_clip.take());
+        self.state = State { transform: transform.multiply(&state.transform), clip, svg_clip, ..state.clone() };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The color at (x, y), counted from the bottom-left corner
+    fn pixel(ctx: &Ctx, x: usize, y: usize) -> u32 {
+        ctx.buf[(ctx.h - 1 - y) * ctx.w + x]
+    }
+
+    fn draw(ctx: &mut Ctx) {
+        ctx.clear_rect(0xffffff);
+        ctx.fill_rect(&[5, 5].into(), 20, 10, 0x4328ed);
+        ctx.set_thickness(2);
+        ctx.begin_path();
+        ctx.move_to(10.0, 30.0);
+        ctx.line_to(70.0, 50.0);
+        ctx.stroke(0xff0800);
+        ctx.fill_circle(&[60, 20].into(), 8, 0x1be81b);
+        ctx.draw_text(&[30, 5].into(), "Yo", 1);
+    }
+
+    #[test]
+    fn replay_at_twice_the_size() {
+        let mut small = Ctx::headless(80, 60);
+        small.begin_recording();
+        draw(&mut small);
+        let list = small.end_recording();
+
+        let mut replayed = Ctx::headless(160, 120);
+        list.replay(&mut replayed, Some(Transform::scaling(2.0, 2.0)));
+        let mut direct = Ctx::headless(160, 120);
+        direct.scale(2.0, 2.0);
+        draw(&mut direct);
+        assert!(replayed.buf == direct.buf);
+
+        // Without a transform it's the same as the original
+        let mut again = Ctx::headless(80, 60);
+        list.replay(&mut again, None);
+        assert!(again.buf == small.buf);
+    }
+
+    #[test]
+    fn replay_keeps_the_settings_of_ctx() {
+        let mut ctx = Ctx::headless(40, 40);
+        ctx.begin_recording();
+        ctx.translate(10.0, 10.0);
+        ctx.fill_rect(&[0, 0].into(), 5, 5, 0xffffff);
+        let list = ctx.end_recording();
+
+        // The recorded transform is applied after the one replay is given, not after the one ctx has
+        let mut other = Ctx::headless(40, 40);
+        other.translate(20.0, 0.0);
+        other.set_thickness(3);
+        list.replay(&mut other, Some(Transform::translation(0.0, 20.0)));
+        assert_eq!(pixel(&other, 10, 30), 0xffffff);
+        assert_eq!(pixel(&other, 14, 34), 0xffffff);
+        assert_eq!(pixel(&other, 30, 10), 0);
+        assert_eq!(other.get_transform(), Transform::translation(20.0, 0.0));
+        assert_eq!(other.state.thickness, 3);
+    }
+
+    #[test]
+    fn replay_only_restores_what_it_saved() {
+        let mut ctx = Ctx::headless(20, 20);
+        ctx.begin_recording();
+        ctx.restore();
+        ctx.restore();
+        ctx.save();
+        ctx.translate(5.0, 5.0);
+        let list = ctx.end_recording();
+
+        let mut other = Ctx::headless(20, 20);
+        other.save();
+        other.translate(1.0, 1.0);
+        list.replay(&mut other, None);
+        // The extra restores didn't reach the save made before, and the save that was never restored is gone
+        assert_eq!(other.saved.len(), 1);
+        assert_eq!(other.get_transform(), Transform::translation(1.0, 1.0));
+        other.restore();
+        assert_eq!(other.get_transform(), Transform::identity());
+    }
+
+    #[test]
+    fn replay_clips() {
+        let mut ctx = Ctx::headless(20, 20);
+        ctx.begin_recording();
+        ctx.save();
+        ctx.clip_rect(5.0, 5.0, 5.0, 5.0);
+        ctx.fill_rect(&[0, 0].into(), 20, 20, 0xffffff);
+        ctx.restore();
+        ctx.fill_rect(&[15, 15].into(), 2, 2, 0xff0800);
+        let list = ctx.end_recording();
+
+        let mut big = Ctx::headless(40, 40);
+        list.replay(&mut big, Some(Transform::scaling(2.0, 2.0)));
+        assert!(big.buf == {
+            let mut direct = Ctx::headless(40, 40);
+            direct.scale(2.0, 2.0);
+            direct.save();
+            direct.clip_rect(5.0, 5.0, 5.0, 5.0);
+            direct.fill_rect(&[0, 0].into(), 20, 20, 0xffffff);
+            direct.restore();
+            direct.fill_rect(&[15, 15].into(), 2, 2, 0xff0800);
+            direct.buf
+        });
+        assert_eq!(big.buf.iter().filter(|&&p| p == 0xffffff).count(), 10 * 10);
+        assert_eq!(pixel(&big, 10, 10), 0xffffff);
+        assert_eq!(pixel(&big, 9, 10), 0);
+        // The clip was restored along with the rest, both in the recording and after the replay
+        assert_eq!(pixel(&big, 31, 31), 0xff0800);
+        big.fill_rect(&[0, 0].into(), 1, 1, 0x1be81b);
+        assert_eq!(pixel(&big, 0, 0), 0x1be81b);
+    }
+}
@@ -0,0 +1,363 @@
+// Turning lines into polygons so thick lines can be filled instead of being drawn as a bunch of thin lines.
+// Every piece (segments, joins, caps) is its own polygon, all wound the same way, so filling them together
+// with the nonzero rule gives their union without anything being drawn twice.
+// Round shapes (circles, ellipses, arcs) are also flattened into points here.
+
+use std::f32::consts::PI;
+
+use crate::math::Vec2D;
+use crate::raster;
+
+/// How the ends of a thick line look, like lineCap in js.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LineCap {
+    /// The line stops exactly at the end point
+    Butt,
+    /// A half circle is added to the end
+    Round,
+    /// The line is extended by half its thickness
+    Square,
+}
+
+/// How the corners between two thick lines look, like lineJoin in js.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LineJoin {
+    /// The outer edges are extended until they meet (see `Ctx::set_miter_limit`)
+    Miter,
+    /// The corner is rounded off
+    Round,
+    /// The corner is cut off
+    Bevel,
+}
+
+pub(crate) struct StrokeStyle {
+    pub width: f32,
+    pub cap: LineCap,
+    pub join: LineJoin,
+    pub miter_limit: f32,
+}
+
+/// Gives the polygons making up the outline of a polyline.
+pub(crate) fn stroke_polyline(points: &[Vec2D<f32>], closed: bool, style: &StrokeStyle) -> Vec<Vec<Vec2D<f32>>> {
+    let hw = style.width / 2.0;
+    let mut polygons: Vec<Vec<Vec2D<f32>>> = Vec::new();
+
+    // Points on top of each other have no direction, so they are dropped
+    let mut pts: Vec<Vec2D<f32>> = Vec::with_capacity(points.len());
+    for p in points {
+        if pts.last().is_none_or(|last: &Vec2D<f32>| last.distance(p) > 1e-6) {
+            pts.push(p.clone());
+        }
+    }
+    if closed && pts.len() > 2 && pts[0].distance(&pts[pts.len() - 1]) <= 1e-6 {
+        pts.pop();
+    }
+
+    if pts.len() < 2 {
+        // A line with no length, only the caps are drawn (like js)
+        if let Some(p) = pts.first() {
+            match style.cap {
+                LineCap::Butt => {},
+                LineCap::Round => polygons.push(circle(p, hw)),
+                LineCap::Square => polygons.push(quad(p, &Vec2D::new(1.0, 0.0), -hw, hw, hw)),
+            }
+        }
+        return polygons;
+    }
+
+    let n = pts.len();
+    let segment_count = if closed { n } else { n - 1 };
+    for i in 0..segment_count {
+        let (a, b) = (&pts[i], &pts[(i + 1) % n]);
+        let dir = b.sub_vec(a).normalize();
+        let length = b.sub_vec(a).magnitude().sqrt();
+        polygons.push(quad(a, &dir, 0.0, length, hw));
+    }
+
+    // Joins
+    let join_points = if closed { 0..n } else { 1..n - 1 };
+    for i in join_points {
+        let prev = &pts[(i + n - 1) % n];
+        let p = &pts[i];
+        let next = &pts[(i + 1) % n];
+        if let Some(join) = join(prev, p, next, hw, style) {
+            polygons.push(join);
+        }
+    }
+
+    // Caps
+    if !closed {
+        let start_dir = pts[0].sub_vec(&pts[1]).normalize();
+        let end_dir = pts[n - 1].sub_vec(&pts[n - 2]).normalize();
+        for (p, dir) in [(&pts[0], start_dir), (&pts[n - 1], end_dir)] {
+            match style.cap {
+                LineCap::Butt => {},
+                LineCap::Round => polygons.push(circle(p, hw)),
+                LineCap::Square => polygons.push(quad(p, &dir, 0.0, hw, hw)),
+            }
+        }
+    }
+
+    for polygon in polygons.iter_mut() {
+        if signed_area(polygon) < 0.0 {
+            polygon.reverse();
+        }
+    }
+    polygons
+}
+
+/// Cuts a polyline into dashes, like setLineDash in js. pattern is the length of each dash and gap,
+/// starting with a dash, and offset is how far into the pattern the line starts.
+///
+/// Only the dashes inside of bounds ([min x, min y, max x, max y]) are made, the rest of the line just moves
+/// the pattern along. Every dash is its own open polyline, so it gets caps on both ends.
+pub(crate) fn dash_polyline(points: &[Vec2D<f32>], closed: bool, pattern: &[f32], offset: f32, bounds: [f32; 4]) -> Vec<Vec<Vec2D<f32>>> {
+    let total: f32 = pattern.iter().sum();
+    let mut dashes: Vec<Vec<Vec2D<f32>>> = Vec::new();
+    if points.is_empty() || pattern.is_empty() || total <= 0.0 || !total.is_finite() {
+        return dashes;
+    }
+    let mut pts = points.to_vec();
+    if closed && pts.len() > 2 {
+        pts.push(pts[0].clone());
+    }
+
+    // Distances are in f64, f32 can't step along a line that goes far off the screen
+    let pattern: Vec<f64> = pattern.iter().map(|&n| n as f64).collect();
+    let total = total as f64;
+    // Which dash or gap is at phase (0 to total), and how much of it is left
+    let locate = |mut phase: f64| {
+        let mut i = 0;
+        while phase >= pattern[i] && phase > 0.0 {
+            phase -= pattern[i];
+            i = (i + 1) % pattern.len();
+        }
+        (i, pattern[i] - phase)
+    };
+    // Moves the pattern along by skipped, for the parts of the line that are off the screen
+    let skip = |i: usize, remaining: f64, skipped: f64| {
+        if !(skipped.is_finite() && skipped > 0.0) {
+            return (i, remaining);
+        }
+        let phase = pattern[..i].iter().sum::<f64>() + pattern[i] - remaining;
+        locate((phase + skipped).rem_euclid(total))
+    };
+
+    let (mut i, mut remaining) = locate((offset as f64).rem_euclid(total)); // how much is left of the current dash or gap
+    let mut on = i % 2 == 0;
+    let mut dash: Vec<Vec2D<f32>> = if on { vec![pts[0].clone()] } else { Vec::new() };
+
+    for segment in pts.windows(2) {
+        let (a, b) = (&segment[0], &segment[1]);
+        let (dx, dy) = (b.x as f64 - a.x as f64, b.y as f64 - a.y as f64);
+        let length = dx.hypot(dy);
+        let at = |t: f64| {
+            if t >= length { return b.clone() }
+            let t = if length > 0.0 { t / length } else { 0.0 };
+            Vec2D::new((a.x as f64 + t * dx) as f32, (a.y as f64 + t * dy) as f32)
+        };
+
+        let Some((t0, t1)) = raster::clip_range(a, b, bounds) else {
+            if on && dash.len() > 1 {
+                dashes.push(std::mem::take(&mut dash));
+            }
+            (i, remaining) = skip(i, remaining, length);
+            on = i % 2 == 0;
+            dash = if on { vec![b.clone()] } else { Vec::new() };
+            continue;
+        };
+        let (t0, t1) = (t0 * length, t1 * length);
+        if t0 > 0.0 {
+            // The start is off the screen, so a dash can only start where the segment comes onto it
+            (i, remaining) = skip(i, remaining, t0);
+            on = i % 2 == 0;
+            dash = if on { vec![at(t0)] } else { Vec::new() };
+        }
+
+        let mut t = t0; // how far along the segment we are
+        while t1 - t > remaining {
+            // Stops if the dashes are too short to make any progress, instead of going on forever
+            if t + remaining <= t && remaining > 0.0 {
+                break;
+            }
+            t += remaining;
+            let p = at(t);
+            if on {
+                dash.push(p);
+                dashes.push(std::mem::take(&mut dash));
+            }else {
+                dash = vec![p];
+            }
+            on = !on;
+            i = (i + 1) % pattern.len();
+            remaining = pattern[i];
+        }
+        remaining -= t1 - t;
+        if on {
+            dash.push(at(t1));
+        }
+        if t1 < length {
+            // The rest of the segment is off the screen, the dash ends here and the pattern goes on without it
+            if on && dash.len() > 1 {
+                dashes.push(std::mem::take(&mut dash));
+            }
+            (i, remaining) = skip(i, remaining, length - t1);
+            on = i % 2 == 0;
+            dash = if on { vec![b.clone()] } else { Vec::new() };
+        }
+    }
+    if on && dash.len() > 1 {
+        dashes.push(dash);
+    }
+    dashes
+}
+
+// The corner piece at p, between the segments prev->p and p->next
+fn join(prev: &Vec2D<f32>, p: &Vec2D<f32>, next: &Vec2D<f32>, hw: f32, style: &StrokeStyle) -> Option<Vec<Vec2D<f32>>> {
+    let d0 = p.sub_vec(prev).normalize();
+    let d1 = next.sub_vec(p).normalize();
+    let cross = d0.x * d1.y - d0.y * d1.x;
+    if cross.abs() < 1e-6 && d0.dot(&d1) > 0.0 {
+        return None; // straight, nothing to fill
+    }
+    if style.join == LineJoin::Round {
+        return Some(circle(p, hw));
+    }
+
+    // The corner sticks out on the side opposite to the turn
+    let side = if cross > 0.0 { -1.0 } else { 1.0 };
+    let n0 = Vec2D::new(-d0.y, d0.x).mult_scalar(side);
+    let n1 = Vec2D::new(-d1.y, d1.x).mult_scalar(side);
+    let a = p.add_vec(&n0.mult_scalar(hw));
+    let b = p.add_vec(&n1.mult_scalar(hw));
+
+    if style.join == LineJoin::Miter {
+        let m = n0.add_vec(&n1).normalize();
+        let cos_half = m.dot(&n0);
+        if cos_half > 1e-6 && 1.0 / cos_half <= style.miter_limit {
+            let tip = p.add_vec(&m.mult_scalar(hw / cos_half));
+            return Some(vec![p.clone(), a, tip, b]);
+        }
+    }
+    // Bevel, or a miter that's too long
+    Some(vec![p.clone(), a, b])
+}
+
+// A rectangle going along dir from `from` to `to` (distances from p), hw thick on each side
+fn quad(p: &Vec2D<f32>, dir: &Vec2D<f32>, from: f32, to: f32, hw: f32) -> Vec<Vec2D<f32>> {
+    let normal = Vec2D::new(-dir.y, dir.x).mult_scalar(hw);
+    let start = p.add_vec(&dir.mult_scalar(from));
+    let end = p.add_vec(&dir.mult_scalar(to));
+    vec![start.sub_vec(&normal), end.sub_vec(&normal), end.add_vec(&normal), start.add_vec(&normal)]
+}
+
+/// A circle as a polygon, with enough points that it looks round at its size.
+pub(crate) fn circle(center: &Vec2D<f32>, radius: f32) -> Vec<Vec2D<f32>> {
+    ellipse(center, radius, radius, 1.0)
+}
+
+/// An ellipse as a polygon (the last point isn't repeated).
+pub(crate) fn ellipse(center: &Vec2D<f32>, rx: f32, ry: f32, pixel_scale: f32) -> Vec<Vec2D<f32>> {
+    let mut points = arc(center, rx, ry, 0.0, 2.0 * PI, pixel_scale);
+    points.pop();
+    points
+}
+
+/// Points along an elliptical arc, going from start_angle to end_angle (radians, counter-clockwise from the x axis).
+/// Both ends are included.
+///
+/// pixel_scale is how many pixels one unit will end up being once it's drawn, so big arcs get more points.
+pub(crate) fn arc(center: &Vec2D<f32>, rx: f32, ry: f32, start_angle: f32, end_angle: f32, pixel_scale: f32) -> Vec<Vec2D<f32>> {
+    let sweep = (end_angle - start_angle).clamp(-2.0 * PI, 2.0 * PI);
+    // About one point every 2 pixels
+    let full_steps = (PI * rx.max(ry) * pixel_scale).ceil().clamp(8.0, 256.0);
+    let steps = ((full_steps * sweep.abs() / (2.0 * PI)).ceil() as usize).max(1);
+
+    (0..=steps).map(|i| {
+        let t = start_angle + sweep * i as f32 / steps as f32;
+        Vec2D::new(center.x + rx * t.cos(), center.y + ry * t.sin())
+    }).collect()
+}
+
+fn signed_area(polygon: &[Vec2D<f32>]) -> f32 {
+    let n = polygon.len();
+    (0..n).map(|i| {
+        let (a, b) = (&polygon[i], &polygon[(i + 1) % n]);
+        a.x * b.y - b.x * a.y
+    }).sum::<f32>() / 2.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Ctx;
+
+    const EVERYWHERE: [f32; 4] = [-1e9, -1e9, 1e9, 1e9];
+
+    // The dashes of a horizontal line, as the x at the start and end of every dash
+    fn spans(dashes: &[Vec<Vec2D<f32>>]) -> Vec<(f32, f32)> {
+        dashes.iter().map(|dash| (dash[0].x, dash[dash.len() - 1].x)).collect()
+    }
+
+    fn line(x0: f32, x1: f32) -> Vec<Vec2D<f32>> {
+        vec![Vec2D::new(x0, 5.0), Vec2D::new(x1, 5.0)]
+    }
+
+    #[test]
+    fn dash_pattern() {
+        let dashes = dash_polyline(&line(0.0, 10.0), false, &[2.0, 2.0], 0.0, EVERYWHERE);
+        assert_eq!(spans(&dashes), [(0.0, 2.0), (4.0, 6.0), (8.0, 10.0)]);
+        // The pattern goes on around corners
+        let corner = [Vec2D::new(0.0, 0.0), Vec2D::new(3.0, 0.0), Vec2D::new(3.0, 3.0)];
+        let dashes = dash_polyline(&corner, false, &[2.0, 2.0], 0.0, EVERYWHERE);
+        let points: Vec<Vec<(f32, f32)>> = dashes.iter().map(|dash| dash.iter().map(|p| (p.x, p.y)).collect()).collect();
+        assert_eq!(points, [[(0.0, 0.0), (2.0, 0.0)], [(3.0, 1.0), (3.0, 3.0)]]);
+    }
+
+    #[test]
+    fn dash_offset() {
+        let dashes = dash_polyline(&line(0.0, 10.0), false, &[2.0, 2.0], 1.0, EVERYWHERE);
+        assert_eq!(spans(&dashes), [(0.0, 1.0), (3.0, 5.0), (7.0, 9.0)]);
+        // Negative offsets go the other way, -1 is the same as 3
+        let dashes = dash_polyline(&line(0.0, 10.0), false, &[2.0, 2.0], -1.0, EVERYWHERE);
+        assert_eq!(spans(&dashes), [(1.0, 3.0), (5.0, 7.0), (9.0, 10.0)]);
+        assert_eq!(spans(&dashes), spans(&dash_polyline(&line(0.0, 10.0), false, &[2.0, 2.0], 3.0, EVERYWHERE)));
+    }
+
+    #[test]
+    fn clipped_dashes_keep_their_phase() {
+        let bounds = [-10.0, -10.0, 110.0, 110.0];
+        for (x0, x1) in [(-1000.5, 100.0), (500.0, -1000.25), (-1e6, 1e6)] {
+            let clipped = spans(&dash_polyline(&line(x0, x1), false, &[3.0, 2.0], 0.5, bounds));
+            let whole = spans(&dash_polyline(&line(x0, x1), false, &[3.0, 2.0], 0.5, EVERYWHERE));
+            // Cut to the bounds, the whole line has the same dashes there
+            let inside: Vec<(f32, f32)> = whole.iter()
+                .map(|&(a, b)| (a.clamp(-10.0, 110.0), b.clamp(-10.0, 110.0)))
+                .filter(|(a, b)| a != b)
+                .collect();
+            assert_eq!(clipped.len(), inside.len());
+            for (c, i) in clipped.iter().zip(&inside) {
+                assert!((c.0 - i.0).abs() < 0.01 && (c.1 - i.1).abs() < 0.01, "{c:?} vs {i:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn very_long_dashed_line() {
+        // Used to walk all 5e7 dashes, and never finish once f32 couldn't step along the line anymore
+        let dashes = dash_polyline(&line(-1e8, 1e8), false, &[2.0, 2.0], 0.0, [-4.0, -4.0, 104.0, 104.0]);
+        assert_eq!(dashes.len(), 27);
+        assert!(dashes.iter().flatten().all(|p| (-4.0..=104.0).contains(&p.x)));
+
+        // 1e8 is a whole number of patterns, so on the screen it's the same as a line starting at 0
+        let draw = |start: f32| {
+            let mut ctx = Ctx::headless(100, 100);
+            ctx.set_line_dash(&[2.0, 2.0]);
+            ctx.draw_line_f(&Vec2D::new(start, 5.0), &Vec2D::new(1e8, 5.0), 0xffffff);
+            ctx.buf
+        };
+        let buf = draw(-1e8);
+        assert!(buf.contains(&0xffffff));
+        assert_eq!(buf, draw(0.0));
+    }
+}
@@ -0,0 +1,386 @@
+// Drawing to an SVG document instead of the pixel buffer, so plots can be exported as vector figures.
+// The Ctx primitives end up in stroke_lines, fill_shapes and draw_text, which send everything here when
+// the target is Svg. Shapes are transformed before they get here, so the SVG never needs a transform
+// (except for rotated text).
+
+use std::fmt::Write as _;
+use std::path::Path;
+
+use crate::gradient::{Gradient, GradientKind};
+use crate::math::{Transform, Vec2D};
+use crate::text::Text;
+use crate::{Ctx, Error, FillRule, LineCap, LineJoin, Origin};
+
+/// Where a Ctx draws to, see `Ctx::set_target`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Target {
+    /// Pixels in the buffer, shown by SnorfWindow or saved as a PNG. This is the default.
+    Pixels,
+    /// An SVG document, get it with to_svg or save_svg. The buffer isn't touched.
+    Svg,
+}
+
+#[derive(Default)]
+pub(crate) struct Svg {
+    background: Option<u32>,
+    defs: Vec<String>, // clip paths and gradients
+    elements: Vec<String>,
+}
+
+// Whether a shape is outlined or filled in
+pub(crate) enum Paint {
+    Stroke,
+    Fill(FillRule),
+}
+
+impl Ctx {
+    /// Switches between drawing pixels and drawing an SVG. Switching to Svg starts an empty document,
+    /// after that all of the usual drawing calls (lines, rects, shapes, paths, text, and the GraphCtx axes
+    /// and graphs) become SVG elements. Images aren't part of the SVG. Text in the built-in bitmap font is drawn as
+    /// its pixels, so it looks the same as in the buffer without the viewer needing the font.
+    /// ```
+    /// # use bml_grapher::{Ctx, Target};
+    /// let mut ctx = Ctx::headless(320, 240);
+    /// ctx.set_target(Target::Svg);
+    /// ctx.clear_rect(0xffffff);
+    /// ctx.draw_line(&[10,10].into(), &[300,200].into(), 0xff0800).unwrap();
+    /// let svg = ctx.to_svg();
+    /// assert!(svg.contains("<line"));
+    /// ```
+    pub fn set_target(&mut self, target: Target) {
+        self.svg = match target {
+            Target::Pixels => None,
+            Target::Svg => Some(Svg::default()),
+        };
+    }
+    pub fn get_target(&self) -> Target {
+        if self.svg.is_some() { Target::Svg } else { Target::Pixels }
+    }
+
+    /// The SVG document drawn so far (an empty one if the target isn't Svg).
+    pub fn to_svg(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, r#"<svg xmlns="http://www.w3.org/2000/svg" width="{0}" height="{1}" viewBox="0 0 {0} {1}">"#, self.w, self.h);
+        if let Some(svg) = &self.svg {
+            if !svg.defs.is_empty() {
+                let _ = writeln!(out, "<defs>\n{}\n</defs>", svg.defs.join("\n"));
+            }
+            if let Some(background) = svg.background {
+                let _ = writeln!(out, r#"<rect width="100%" height="100%" fill="{}"/>"#, hex(background));
+            }
+            for element in &svg.elements {
+                let _ = writeln!(out, "{element}");
+            }
+        }
+        out.push_str("</svg>\n");
+        out
+    }
+
+    pub fn save_svg<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        Ok(std::fs::write(path, self.to_svg())?)
+    }
+
+    // Starts over with a plain background (or none), like clear_rect does with the pixels
+    pub(crate) fn svg_clear(&mut self, background: Option<u32>) {
+        if let Some(svg) = &mut self.svg {
+            svg.elements.clear();
+            svg.background = background;
+        }
+    }
+
+    // Adds lines or shapes (already transformed). A single line becomes a <line>, a rectangle a <rect>
+    // and everything else a <path>.
+    pub(crate) fn svg_shape(&mut self, lines: &[(Vec<Vec2D<f32>>, bool)], paint: Paint, color: u32, alpha: f32) {
+        self.svg_element(lines, paint, &hex(color), alpha);
+    }
+
+    // Adds filled shapes (already transformed) with a gradient, which is placed with the current transform
+    pub(crate) fn svg_gradient_shape(&mut self, contours: &[Vec<Vec2D<f32>>], rule: FillRule, gradient: &Gradient) {
+        // From the gradient's coordinates to the screen, then to the SVG's
+        let (x0, y0) = self.svg_xy(&Vec2D::new(0.0, 0.0));
+        let flip = if self.state.origin == Origin::BottomLeft { -1.0 } else { 1.0 };
+        let m = Transform::new(1.0, 0.0, 0.0, flip, x0, y0).multiply(&self.state.transform);
+        let Some(svg) = &mut self.svg else { return };
+
+        let id = format!("gradient{}", svg.defs.len());
+        let placement = format!(r#"gradientUnits="userSpaceOnUse" gradientTransform="{}""#, matrix(&m));
+        let mut def = match gradient.kind {
+            GradientKind::Linear { x0, y0, x1, y1 } => format!(
+                r#"<linearGradient id="{id}" x1="{}" y1="{}" x2="{}" y2="{}" {placement}>"#, num(x0), num(y0), num(x1), num(y1)
+            ),
+            GradientKind::Radial { x0, y0, r0, x1, y1, r1 } => format!(
+                r#"<radialGradient id="{id}" fx="{}" fy="{}" fr="{}" cx="{}" cy="{}" r="{}" {placement}>"#,
+                num(x0), num(y0), num(r0), num(x1), num(y1), num(r1)
+            ),
+        };
+        for (offset, color, alpha) in &gradient.stops {
+            let _ = write!(def, r#"<stop offset="{}" stop-color="{}" stop-opacity="{}"/>"#, num(*offset), hex(*color), num(*alpha));
+        }
+        def.push_str(match gradient.kind {
+            GradientKind::Linear { .. } => "</linearGradient>",
+            GradientKind::Radial { .. } => "</radialGradient>",
+        });
+        svg.defs.push(def);
+
+        let lines: Vec<_> = contours.iter().map(|contour| (contour.clone(), true)).collect();
+        self.svg_element(&lines, Paint::Fill(rule), &format!("url(#{id})"), 1.0);
+    }
+
+    fn svg_element(&mut self, lines: &[(Vec<Vec2D<f32>>, bool)], paint: Paint, color: &str, alpha: f32) {
+        let points: Vec<Vec<(f32, f32)>> = lines.iter()
+            .map(|(line, _)| line.iter().map(|p| self.svg_xy(p)).collect())
+            .collect();
+        let style = self.svg_style(&paint, color, alpha);
+
+        let element = match (&points[..], lines) {
+            ([line], [(_, false)]) if line.len() == 2 && matches!(paint, Paint::Stroke) => {
+                let [(x1, y1), (x2, y2)] = [line[0], line[1]];
+                format!(r#"<line x1="{}" y1="{}" x2="{}" y2="{}" {style}/>"#, num(x1), num(y1), num(x2), num(y2))
+            },
+            ([rect], [(_, closed)]) if (*closed || matches!(paint, Paint::Fill(_))) && is_rect(rect) => {
+                let (x, y) = (rect[0].0.min(rect[2].0), rect[0].1.min(rect[2].1));
+                let (w, h) = ((rect[0].0 - rect[2].0).abs(), (rect[0].1 - rect[2].1).abs());
+                format!(r#"<rect x="{}" y="{}" width="{}" height="{}" {style}/>"#, num(x), num(y), num(w), num(h))
+            },
+            _ => {
+                let mut d = String::new();
+                for (line, (_, closed)) in points.iter().zip(lines) {
+                    for (i, (x, y)) in line.iter().enumerate() {
+                        let _ = write!(d, "{}{} {} ", if i == 0 { "M" } else { "L" }, num(*x), num(*y));
+                    }
+                    if *closed || matches!(paint, Paint::Fill(_)) {
+                        d.push_str("Z ");
+                    }
+                }
+                if d.is_empty() {
+                    return;
+                }
+                format!(r#"<path d="{}" {style}/>"#, d.trim_end())
+            },
+        };
+        if let Some(svg) = &mut self.svg {
+            svg.elements.push(element);
+        }
+    }
+
+    // Text in the bitmap font is drawn as its pixels, SVG viewers don't have the font so a <text> wouldn't look
+    // like the PNG. Every run of pixels in a row becomes a rectangle, and all of them together one path.
+    pub(crate) fn svg_text(&mut self, pos: &Vec2D<usize>, text: &str, scale: usize, color: u32, alpha: f32) {
+        let mut text_obj = Text::new(self.w, self.h, scale, true);
+        text_obj.set_line_spacing(self.state.line_spacing);
+        text_obj.set_replacement(self.state.replacement);
+        let mut runs: Vec<(usize, usize, usize)> = Vec::new(); // x, y, length (top-down)
+        text_obj.for_each_pixel((0, 0), text, |x, y, _| match runs.last_mut() {
+            Some((rx, ry, length)) if *ry == y && *rx + *length == x => *length += 1,
+            _ => runs.push((x, y, 1)),
+        });
+
+        // The text goes right and down from its top-left corner, where that ends up depends on the origin,
+        // the text align and baseline and the transform
+        let place = self.text_placement(pos, text, scale);
+        let contours: Vec<(Vec<Vec2D<f32>>, bool)> = runs.iter().map(|&(x, y, length)| {
+            let [x0, y0, x1, y1] = [x as f32, y as f32, (x + length) as f32, (y + 1) as f32];
+            (self.to_device(&[place(x0, y0), place(x1, y0), place(x1, y1), place(x0, y1)]), true)
+        }).collect();
+        if !contours.is_empty() {
+            self.svg_shape(&contours, Paint::Fill(FillRule::NonZero), color, alpha);
+        }
+    }
+
+    // Adds a clip path (already transformed) on top of the current one, and uses it for everything after
+    pub(crate) fn svg_clip(&mut self, contours: &[Vec<Vec2D<f32>>], rule: FillRule) {
+        let mut d = String::new();
+        for contour in contours {
+            for (i, p) in contour.iter().enumerate() {
+                let (x, y) = self.svg_xy(p);
+                let _ = write!(d, "{}{} {} ", if i == 0 { "M" } else { "L" }, num(x), num(y));
+            }
+            d.push_str("Z ");
+        }
+        let Some(svg) = &mut self.svg else { return };
+        let id = format!("clip{}", svg.defs.len());
+        // A clip path can be clipped itself, that's how they are stacked
+        let previous = match &self.state.svg_clip {
+            Some(previous) => format!(r#" clip-path="url(#{previous})""#),
+            None => String::new(),
+        };
+        let rule = if rule == FillRule::EvenOdd { "evenodd" } else { "nonzero" };
+        svg.defs.push(format!(
+            r#"<clipPath id="{id}"{previous}><path d="{}" clip-rule="{rule}"/></clipPath>"#,
+            d.trim_end()
+        ));
+        self.state.svg_clip = Some(id);
+    }
+
+    // Pixel centers are at whole numbers and the SVG starts at the top-left corner of the screen
+    fn svg_xy(&self, p: &Vec2D<f32>) -> (f32, f32) {
+        match self.state.origin {
+            Origin::BottomLeft => (p.x + 0.5, self.h as f32 - 0.5 - p.y),
+            Origin::TopLeft => (p.x + 0.5, p.y + 0.5),
+        }
+    }
+
+    // The attributes for the color and the current line style, opacity and clip
+    fn svg_style(&self, paint: &Paint, color: &str, alpha: f32) -> String {
+        let mut style = String::new();
+        match paint {
+            Paint::Fill(rule) => {
+                let _ = write!(style, r#"fill="{color}""#);
+                if *rule == FillRule::EvenOdd {
+                    style.push_str(r#" fill-rule="evenodd""#);
+                }
+            },
+            Paint::Stroke => {
+                let scale = self.state.transform.scale_factor();
+                let width = (self.state.thickness as f32 * scale).max(1.0);
+                let _ = write!(style, r#"fill="none" stroke="{color}" stroke-width="{}""#, num(width));
+                match self.state.line_cap {
+                    LineCap::Butt => {},
+                    LineCap::Round => style.push_str(r#" stroke-linecap="round""#),
+                    LineCap::Square => style.push_str(r#" stroke-linecap="square""#),
+                }
+                match self.state.line_join {
+                    LineJoin::Miter => { let _ = write!(style, r#" stroke-miterlimit="{}""#, num(self.state.miter_limit)); },
+                    LineJoin::Round => style.push_str(r#" stroke-linejoin="round""#),
+                    LineJoin::Bevel => style.push_str(r#" stroke-linejoin="bevel""#),
+                }
+                if self.state.line_dash.iter().sum::<f32>() > 0.0 {
+                    let dash: Vec<String> = self.state.line_dash.iter().map(|s| num(s * scale)).collect();
+                    let _ = write!(style, r#" stroke-dasharray="{}""#, dash.join(" "));
+                    if self.state.line_dash_offset != 0.0 {
+                        let _ = write!(style, r#" stroke-dashoffset="{}""#, num(self.state.line_dash_offset * scale));
+                    }
+                }
+            },
+        }
+        let opacity = alpha * self.state.global_alpha;
+        if opacity < 1.0 {
+            let _ = write!(style, r#" opacity="{}""#, num(opacity));
+        }
+        if let Some(clip) = &self.state.svg_clip {
+            let _ = write!(style, r#" clip-path="url(#{clip})""#);
+        }
+        style
+    }
+}
+
+// 4 corners going around an axis-aligned rectangle
+fn is_rect(p: &[(f32, f32)]) -> bool {
+    p.len() == 4 && (
+        (p[0].1 == p[1].1 && p[1].0 == p[2].0 && p[2].1 == p[3].1 && p[3].0 == p[0].0) ||
+        (p[0].0 == p[1].0 && p[1].1 == p[2].1 && p[2].0 == p[3].0 && p[3].1 == p[0].1)
+    )
+}
+
+fn hex(color: u32) -> String {
+    format!("#{:06x}", color & 0xffffff)
+}
+
+// Numbers with at most 2 decimals and no trailing zeros
+fn num(n: f32) -> String {
+    decimals(n, 2)
+}
+
+fn decimals(n: f32, decimals: usize) -> String {
+    let s = format!("{n:.decimals$}");
+    let s = s.trim_end_matches('0').trim_end_matches('.');
+    if s == "-0" { "0".to_string() } else { s.to_string() }
+}
+
+// The scaling and rotation need more decimals, they get multiplied by the coordinates
+fn matrix(m: &Transform) -> String {
+    let [a, b, c, d] = [m.a, m.b, m.c, m.d].map(|n| decimals(n, 5));
+    format!("matrix({a} {b} {c} {d} {} {})", num(m.e), num(m.f))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Ctx, Target};
+
+    fn svg_ctx() -> Ctx {
+        let mut ctx = Ctx::headless(100, 50);
+        ctx.set_target(Target::Svg);
+        ctx.clear_rect(0xffffff);
+        ctx
+    }
+
+    fn elements(ctx: &Ctx) -> Vec<String> {
+        // Without the <svg> around it and the background
+        let svg = ctx.to_svg();
+        let lines: Vec<&str> = svg.lines().collect();
+        assert_eq!(lines[1], r##"<rect width="100%" height="100%" fill="#ffffff"/>"##);
+        lines[2..lines.len() - 1].iter().map(|line| line.to_string()).collect()
+    }
+
+    #[test]
+    fn line() {
+        let mut ctx = svg_ctx();
+        ctx.draw_line(&[10,10].into(), &[60,30].into(), 0xff0800).unwrap();
+        // y goes down in the SVG, and the middle of a pixel is half a pixel in
+        assert_eq!(elements(&ctx), [
+            r##"<line x1="10.5" y1="39.5" x2="60.5" y2="19.5" fill="none" stroke="#ff0800" stroke-width="1" stroke-miterlimit="10"/>"##,
+        ]);
+    }
+
+    #[test]
+    fn rects() {
+        let mut ctx = svg_ctx();
+        ctx.rect(&[5,5].into(), 20, 10, 0x4328ed);
+        ctx.fill_rect(&[30,5].into(), 20, 10, 0x00ff00);
+        assert_eq!(elements(&ctx), [
+            r##"<rect x="5.5" y="34.5" width="20" height="10" fill="none" stroke="#4328ed" stroke-width="1" stroke-miterlimit="10"/>"##,
+            r##"<rect x="30.5" y="34.5" width="20" height="10" fill="#00ff00"/>"##,
+        ]);
+    }
+
+    #[test]
+    fn clear_starts_over() {
+        let mut ctx = svg_ctx();
+        ctx.rect(&[5,5].into(), 20, 10, 0x4328ed);
+        ctx.clear_rect(0xffffff);
+        assert!(elements(&ctx).is_empty());
+        assert!(ctx.to_svg().ends_with("</svg>\n"));
+    }
+
+    // The pixels covered by the rectangles of a path, as an image like the buffer
+    fn path_pixels(svg: &str, (w, h): (usize, usize)) -> Vec<bool> {
+        let d = svg.split(r#"d=""#).nth(1).unwrap().split('"').next().unwrap();
+        let mut covered = vec![false; w * h];
+        for contour in d.split('Z').filter(|c| !c.trim().is_empty()) {
+            let numbers: Vec<f32> = contour.split(['M', 'L', ' '])
+                .filter(|n| !n.is_empty())
+                .map(|n| n.parse().unwrap())
+                .collect();
+            let xs = numbers.iter().step_by(2);
+            let ys = numbers.iter().skip(1).step_by(2);
+            let [x0, x1] = [xs.clone().fold(f32::MAX, |a, b| a.min(*b)), xs.fold(f32::MIN, |a, b| a.max(*b))];
+            let [y0, y1] = [ys.clone().fold(f32::MAX, |a, b| a.min(*b)), ys.fold(f32::MIN, |a, b| a.max(*b))];
+            for y in y0 as usize..y1 as usize {
+                for x in x0 as usize..x1 as usize {
+                    covered[y * w + x] = true;
+                }
+            }
+        }
+        covered
+    }
+
+    #[test]
+    fn bitmap_text_is_its_pixels() {
+        for translate in [(0.0, 0.0), (3.0, -2.0)] {
+            let draw = |target: Target| {
+                let mut ctx = Ctx::headless(60, 30);
+                ctx.set_target(target);
+                ctx.clear_rect(0xffffff);
+                ctx.translate(translate.0, translate.1);
+                ctx.draw_text(&[2,25].into(), "Hi!\n\u{e9}g", 1);
+                ctx
+            };
+            let pixels = draw(Target::Pixels);
+            let lit: Vec<bool> = pixels.buf.iter().map(|&p| p != 0xffffff).collect();
+            let svg = draw(Target::Svg).to_svg();
+            assert!(!svg.contains("<text"));
+            assert!(lit.contains(&true));
+            assert_eq!(path_pixels(&svg, (60, 30)), lit);
+        }
+    }
+}
@@ -2,13 +2,41 @@
 const OFF_COLOR: u32 =  0xFFFFFFFF;
 const ON_COLOR: u32 =  0x00000000;
 
+/// Where text is placed sideways from the position it's drawn at, like textAlign in js.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TextAlign {
+    /// The text starts at the position. This is the default.
+    Left,
+    Center,
+    /// The text ends at the position.
+    Right,
+}
+
+/// Where text is placed up or down from the position it's drawn at, like textBaseline in js.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TextBaseline {
+    /// The top of the text is at the position. This is the default.
+    Top,
+    Middle,
+    /// The bottom of the text is at the position (for fonts, below letters like g and y).
+    Bottom,
+}
+
+// Tabs go to the next multiple of this many characters
+const TAB_SIZE: usize = 4;
+
 /// Default texture is the MICROKNIGHT_FONT
 pub struct Text {
     texture: Vec<u32>,
     width: usize,
-    // height: usize,
+    height: usize,
     scale: usize,
     ignore_off_colors: bool,
+    color: u32,
+    background: Option<(u32, f32)>, // (color, alpha)
+    clip: Option<[usize; 4]>, // [x, y, width, height]
+    line_spacing: usize, // empty rows between lines (at scale 1)
+    replacement: char, // drawn instead of characters the font doesn't have
 }
 #[inline(always)]
 fn color_from_bit(bit: u8) -> u32 {
@@ -20,7 +48,7 @@ fn color_from_bit(bit: u8) -> u32 {
 }
 impl Text {
     /// If ignore_off colors is on, then we won't draw the background colors for the font
-    pub fn new(width: usize, _height: usize, scale: usize, ignore_off_colors: bool) -> Self {
+    pub fn new(width: usize, height: usize, scale: usize, ignore_off_colors: bool) -> Self {
         // Unpack texture for easier drawing
         let mut texture = Vec::with_capacity(128 * 128);
 
@@ -38,40 +66,149 @@ impl Text {
         Self {
             texture,
             width,
-            // height,
+            height,
             scale,
             ignore_off_colors,
+            color: ON_COLOR,
+            background: None,
+            clip: None,
+            line_spacing: 1,
+            replacement: '?',
         }
     }
 
-    pub fn draw(&self, screen: &mut [u32], (mut x, y): (usize, usize), text: &str) {
-        for c in text.chars() {
-            let mut index = c as usize - ' ' as usize;
-            if index > MICROKNIGHT_LAYOUT.len() as usize {
-                index = 0;
+    /// How many empty rows of pixels (at scale 1) are between lines of text, 1 by default.
+    pub fn set_line_spacing(&mut self, spacing: usize) {
+        self.line_spacing = spacing;
+    }
+    /// What's drawn instead of characters the font doesn't have (it has ASCII and Latin-1), '?' by default.
+    /// If the replacement isn't in the font either, they're left blank.
+    pub fn set_replacement(&mut self, replacement: char) {
+        self.replacement = replacement;
+    }
+
+    /// How wide and tall the text is in pixels, with every line.
+    pub fn measure(&self, text: &str) -> (usize, usize) {
+        measure(text, self.scale, self.line_spacing, self.replacement)
+    }
+
+    /// The color of the letters, black by default
+    pub fn set_color(&mut self, color: u32) {
+        self.color = color;
+    }
+    /// Fills the box behind the letters with color, blended with what's on the screen by alpha (0.0 to 1.0).
+    /// None goes back to white, or nothing with ignore_off_colors.
+    pub fn set_background(&mut self, background: Option<(u32, f32)>) {
+        self.background = background;
+    }
+
+    /// Only draws inside of clip = [x, y, width, height] (y counting from the top), None for the whole screen.
+    pub fn set_clip(&mut self, clip: Option<[usize; 4]>) {
+        self.clip = clip;
+    }
+
+    /// Draws the text with its top-left corner at pos. Whatever doesn't fit on the screen (or in the clip) is
+    /// cut off, a pixel at a time.
+    pub fn draw(&self, screen: &mut [u32], pos: (usize, usize), text: &str) {
+        let [cx, cy, cw, ch] = self.clip.unwrap_or([0, 0, self.width, self.height]);
+        let inside = |x: usize, y: usize| {
+            x < self.width && y < self.height && x >= cx && y >= cy && x - cx < cw && y - cy < ch
+        };
+        // The background pixels are needed too, to blend them
+        let ignore_off_colors = self.ignore_off_colors && self.background.is_none();
+        self.glyph_pixels(pos, text, ignore_off_colors, |x, y, color| {
+            if !inside(x, y) {
+                return;
             }
+            let Some(pixel) = screen.get_mut(y * self.width + x) else { return };
+            *pixel = match (color, self.background) {
+                (ON_COLOR, _) => self.color,
+                (_, Some((background, alpha))) => crate::blend(*pixel, background, alpha),
+                _ => color,
+            };
+        });
+    }
 
-            let (layout_x, layout_y) = MICROKNIGHT_LAYOUT[index];
-            let texture_offset = layout_x as usize + (layout_y as usize * 128);
-
-            for fy in 0..8 * self.scale {
-                let ty = fy / self.scale;
-                for fx in 0..8 * self.scale {
-                    let tx = fx / self.scale;
-                    let pixel = texture_offset + (ty * 128) + tx;
-                    if pixel != 0 {
-                        if !(self.ignore_off_colors==true && self.texture[pixel] == OFF_COLOR) { ////! MY STUFF
-                            screen[((y + fy) * self.width) + fx + x] = self.texture[pixel];
-                        }
-                    }
+    /// Calls `f(x, y, color)` for every pixel of the text that would be drawn, instead of writing to a screen.
+    /// The coordinates are top-down, like in `draw`.
+    pub fn for_each_pixel<F: FnMut(usize, usize, u32)>(&self, pos: (usize, usize), text: &str, f: F) {
+        self.glyph_pixels(pos, text, self.ignore_off_colors, f);
+    }
+
+    fn glyph_pixels<F: FnMut(usize, usize, u32)>(&self, (x, y): (usize, usize), text: &str, ignore_off_colors: bool, mut f: F) {
+        // Saturating, set_line_spacing takes any usize and the lines past the end are off any screen anyway
+        let line_height = 8usize.saturating_add(self.line_spacing).saturating_mul(self.scale);
+        for (line_index, line) in lines(text, self.replacement, has_glyph).iter().enumerate() {
+            let y = y.saturating_add(line_index.saturating_mul(line_height));
+            for (column, c) in line.chars().enumerate() {
+                // Only the replacement can be missing here, and then it's blank
+                if let Some(index) = glyph_index(c) {
+                    let x = x.saturating_add(column.saturating_mul(8 * self.scale));
+                    self.glyph(index, (x, y), ignore_off_colors, &mut f);
                 }
             }
+        }
+    }
 
-            x += 8 * self.scale;
+    fn glyph<F: FnMut(usize, usize, u32)>(&self, index: usize, (x, y): (usize, usize), ignore_off_colors: bool, f: &mut F) {
+        let (layout_x, layout_y) = MICROKNIGHT_LAYOUT[index];
+        let texture_offset = layout_x as usize + (layout_y as usize * 128);
+
+        for fy in 0..8 * self.scale {
+            let ty = fy / self.scale;
+            for fx in 0..8 * self.scale {
+                let tx = fx / self.scale;
+                let pixel = texture_offset + (ty * 128) + tx;
+                if pixel != 0 && !(ignore_off_colors && self.texture[pixel] == OFF_COLOR) { ////! MY STUFF
+                    // Past the end of usize is off any screen
+                    if let (Some(px), Some(py)) = (x.checked_add(fx), y.checked_add(fy)) {
+                        f(px, py, self.texture[pixel]);
+                    }
+                }
+            }
         }
     }
 }
 
+// Where the glyph of c is in MICROKNIGHT_LAYOUT, which starts at space and goes up to 255 (Latin-1).
+// The control characters in there (127 to 159) aren't glyphs.
+fn glyph_index(c: char) -> Option<usize> {
+    let index = (c as usize).checked_sub(' ' as usize)?;
+    (index < MICROKNIGHT_LAYOUT.len() && !matches!(c, '\u{7f}'..='\u{9f}')).then_some(index)
+}
+
+pub(crate) fn has_glyph(c: char) -> bool {
+    glyph_index(c).is_some()
+}
+
+// The lines of the text as they are drawn: tabs become spaces up to the next multiple of TAB_SIZE, other control
+// characters (like \r) are left out, and characters that has_glyph says aren't in the font become the replacement.
+pub(crate) fn lines(text: &str, replacement: char, has_glyph: impl Fn(char) -> bool) -> Vec<String> {
+    text.split('\n').map(|line| {
+        let mut out = String::new();
+        let mut columns = 0;
+        for c in line.chars() {
+            if c == '\t' {
+                let spaces = TAB_SIZE - columns % TAB_SIZE;
+                out.extend(std::iter::repeat_n(' ', spaces));
+                columns += spaces;
+            }else if !c.is_control() {
+                out.push(if has_glyph(c) { c } else { replacement });
+                columns += 1;
+            }
+        }
+        out
+    }).collect()
+}
+
+// The size of the text in the bitmap font, see Text::measure
+pub(crate) fn measure(text: &str, scale: usize, line_spacing: usize, replacement: char) -> (usize, usize) {
+    let lines = lines(text, replacement, has_glyph);
+    let columns = lines.iter().map(|line| line.chars().count()).max().unwrap_or(0);
+    let height = (lines.len() * 8).saturating_add((lines.len() - 1).saturating_mul(line_spacing));
+    (columns.saturating_mul(8 * scale), height.saturating_mul(scale))
+}
+
 // Microknight font (128x128 packed with 1 bit per pixel)
 #[rustfmt::skip]
 static MICROKNIGHT_FONT: [u8; ((128 * 128) / u8::BITS) as usize] = [
@@ -227,4 +364,47 @@ static MICROKNIGHT_LAYOUT: [(u8, u8); 224] = [
     (0, 108), (9, 108), (18, 108), (27, 108), (36, 108), (45, 108), (54, 108), (63, 108), (72, 108), (81, 108), (90, 108),
     (99, 108), (108, 108), (117, 108), (0, 117), (9, 117), (18, 117), (27, 117), (36, 117), (45, 117), (54, 117), (63, 117),
     (72, 117), (81, 117),
-];
\ No newline at end of file
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines_tabs_and_control_characters() {
+        assert_eq!(lines("a\tb\tc", '?', has_glyph), ["a   b   c"]);
+        assert_eq!(lines("abcd\te", '?', has_glyph), ["abcd    e"]);
+        assert_eq!(lines("one\r\ntwo\n", '?', has_glyph), ["one", "two", ""]);
+        assert_eq!(lines("a\u{7}b\u{85}c", '?', has_glyph), ["abc"]);
+    }
+
+    #[test]
+    fn lines_replacement() {
+        // Latin-1 is in the font, anything above 255 isn't
+        assert_eq!(lines("\u{e9}\u{ff} \u{20ac}\u{100}\u{1f600}", '?', has_glyph), ["\u{e9}\u{ff} ???"]);
+        assert_eq!(lines("\u{20ac}", '*', has_glyph), ["*"]);
+        // Which characters there are is up to has_glyph
+        assert_eq!(lines("abc", '_', |c| c != 'b'), ["a_c"]);
+    }
+
+    #[test]
+    fn measure_lines() {
+        assert_eq!(measure("", 1, 1, '?'), (0, 8));
+        assert_eq!(measure("ab\nc", 2, 1, '?'), (32, 34));
+        assert_eq!(measure("a\tb", 1, 0, '?'), (40, 8));
+    }
+
+    #[test]
+    fn huge_line_spacing() {
+        // Used to overflow instead of putting the second line off the screen
+        assert_eq!(measure("a\nb", 1, usize::MAX, '?'), (8, usize::MAX));
+        assert_eq!(measure("a\nb", 3, usize::MAX - 4, '?'), (24, usize::MAX));
+
+        let mut text = Text::new(16, 16, 2, false);
+        text.set_line_spacing(usize::MAX);
+        let mut rows = Vec::new();
+        text.for_each_pixel((0, 0), "!\n!", |_, y, _| rows.push(y));
+        assert!(!rows.is_empty());
+        assert!(rows.iter().all(|&y| !(16..usize::MAX - 16).contains(&y)));
+    }
+}